@@ -3,3 +3,64 @@ mod openai;
 
 pub use ollama::*;
 pub use openai::*;
+
+use bytes::{Bytes, BytesMut};
+use futures::{stream, Stream, StreamExt};
+
+/// Split a chunked http body into lines. Both SSE and NDJSON are line based, but a chunk
+/// may end in the middle of a line, so we buffer until we see a line break.
+pub(crate) fn into_lines<S, E>(body: S) -> impl Stream<Item = anyhow::Result<String>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<anyhow::Error>,
+{
+    stream::unfold(
+        (body, BytesMut::new(), false),
+        |(mut body, mut buf, mut eof)| async move {
+            loop {
+                if let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let line = buf.split_to(pos + 1);
+                    let line = String::from_utf8_lossy(&line).trim_end().to_string();
+                    return Some((Ok(line), (body, buf, eof)));
+                }
+                if eof {
+                    if buf.is_empty() {
+                        return None;
+                    }
+                    let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+                    buf.clear();
+                    return Some((Ok(line), (body, buf, eof)));
+                }
+                match body.next().await {
+                    Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+                    Some(Err(e)) => {
+                        eof = true;
+                        buf.clear();
+                        return Some((Err(e.into()), (body, buf, eof)));
+                    }
+                    None => eof = true,
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn into_lines_should_work() {
+        let chunks: Vec<Result<Bytes, anyhow::Error>> = vec![
+            Ok(Bytes::from("data: hel")),
+            Ok(Bytes::from("lo\n\ndata: wor")),
+            Ok(Bytes::from("ld\r\n")),
+            Ok(Bytes::from("done")),
+        ];
+        let lines: Vec<String> = into_lines(stream::iter(chunks))
+            .map(|v| v.unwrap())
+            .collect()
+            .await;
+        assert_eq!(lines, vec!["data: hello", "", "data: world", "done"]);
+    }
+}
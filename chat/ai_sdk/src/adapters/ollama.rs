@@ -1,4 +1,6 @@
-use crate::{AiAdapter, AiService, Message};
+use super::into_lines;
+use crate::{AiAdapter, AiService, CompletionStream, Message};
+use futures::{future, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -35,6 +37,14 @@ pub struct OllamaChatCompletionResponse {
     pub eval_duration: u64,
 }
 
+#[derive(Deserialize)]
+pub struct OllamaChatCompletionChunk {
+    pub model: String,
+    pub created_at: String,
+    pub message: Option<OllamaMessage>,
+    pub done: bool,
+}
+
 impl OllamaAdapter {
     pub fn new(host: impl Into<String>, model: impl Into<String>) -> Self {
         let host = host.into();
@@ -76,6 +86,37 @@ impl AiService for OllamaAdapter {
         let response: OllamaChatCompletionResponse = response.json().await?;
         Ok(response.message.content)
     }
+
+    async fn complete_stream(&self, messages: &[Message]) -> anyhow::Result<CompletionStream> {
+        let request = OllamaChatCompletionRequest {
+            model: self.model.clone(),
+            messages: messages.iter().map(|m| m.into()).collect(),
+            stream: true,
+        };
+        let url = format!("{}/api/chat", self.host);
+        let response = self
+            .client
+            .post(url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // newline delimited json, the last chunk has `done: true`
+        let stream = into_lines(response.bytes_stream())
+            .filter_map(|line| future::ready(parse_chunk(line).transpose()));
+        Ok(stream.boxed())
+    }
+}
+
+fn parse_chunk(line: anyhow::Result<String>) -> anyhow::Result<Option<String>> {
+    let line = line?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let chunk: OllamaChatCompletionChunk = serde_json::from_str(&line)?;
+    let content = chunk.message.map(|m| m.content);
+    Ok(content.filter(|v| !v.is_empty()))
 }
 
 impl From<OllamaAdapter> for AiAdapter {
@@ -106,6 +147,7 @@ impl From<&Message> for OllamaMessage {
 mod tests {
     use super::*;
     use crate::Role;
+    use mock_llm::MockLlm;
 
     #[ignore]
     #[tokio::test]
@@ -118,4 +160,42 @@ mod tests {
         let response = adapter.complete(&messages).await.unwrap();
         println!("response: {}", response);
     }
+
+    #[ignore]
+    #[tokio::test]
+    async fn ollama_complete_stream_should_work() {
+        let adapter = OllamaAdapter::new_local("llama3.2");
+        let messages = vec![Message::user("Hello")];
+        let mut stream = adapter.complete_stream(&messages).await.unwrap();
+        while let Some(delta) = stream.next().await {
+            print!("{}", delta.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn ollama_complete_should_work_with_mock_llm() -> anyhow::Result<()> {
+        let server = MockLlm::new()
+            .when("weather", "It is sunny")
+            .serve()
+            .await?;
+        let adapter = OllamaAdapter::new(server.url(), "llama3.2");
+        let response = adapter
+            .complete(&[Message::user("how is the weather?")])
+            .await?;
+        assert_eq!(response, "It is sunny");
+
+        let stream = adapter
+            .complete_stream(&[Message::user("weather in Tokyo")])
+            .await?;
+        let tokens: Vec<String> = stream.map(|v| v.unwrap()).collect().await;
+        assert_eq!(tokens.concat(), "It is sunny");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_chunk_should_work() {
+        let line = r#"{"model":"llama3.2","created_at":"2024-10-01T00:00:00Z","message":{"role":"assistant","content":"Hi"},"done":false}"#;
+        let content = parse_chunk(Ok(line.to_string())).unwrap();
+        assert_eq!(content.as_deref(), Some("Hi"));
+    }
 }
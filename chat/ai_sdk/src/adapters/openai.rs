@@ -1,5 +1,7 @@
-use crate::{AiAdapter, AiService, Message};
+use super::into_lines;
+use crate::{AiAdapter, AiService, CompletionStream, Message};
 use anyhow::anyhow;
+use futures::{future, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +16,7 @@ pub struct OpenaiAdapter {
 pub struct OpenAIChatCompletionRequest {
     pub model: String,
     pub messages: Vec<OpenAIMessage>,
+    pub stream: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -53,11 +56,65 @@ pub struct OpenAICompletionTokensDetails {
     pub reasoning_tokens: u32,
 }
 
+// OpenAI compatible gateways may leave out everything but the choices in stream chunks
+#[derive(Deserialize)]
+pub struct OpenAIChatCompletionChunk {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub model: String,
+    pub choices: Vec<OpenAIChunkChoice>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAIChunkChoice {
+    #[serde(default)]
+    pub index: u32,
+    pub delta: OpenAIDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAIDelta {
+    pub role: Option<String>,
+    pub content: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OpenAIEmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAIEmbeddingResponse {
+    pub data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAIEmbedding {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
 impl OpenaiAdapter {
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self::new_with_host("https://api.openai.com/v1", api_key, model)
+    }
+
+    /// Use an OpenAI compatible API served at `host`, e.g. a self-hosted gateway
+    pub fn new_with_host(
+        host: impl Into<String>,
+        api_key: impl Into<String>,
+        model: impl Into<String>,
+    ) -> Self {
         let client = Client::new();
         Self {
-            host: "https://api.openai.com/v1".to_string(),
+            host: host.into().trim_end_matches('/').to_string(),
             api_key: api_key.into(),
             model: model.into(),
             client,
@@ -65,11 +122,43 @@ impl OpenaiAdapter {
     }
 }
 
+impl OpenaiAdapter {
+    /// Embed the inputs with an embedding model (e.g. `text-embedding-3-small`), the
+    /// vectors are in the same order as the inputs.
+    pub async fn embed(&self, model: &str, input: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let request = OpenAIEmbeddingRequest {
+            model: model.to_string(),
+            input: input.to_vec(),
+        };
+
+        let url = format!("{}/embeddings", self.host);
+        let response = self
+            .client
+            .post(url)
+            .json(&request)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?
+            .error_for_status()?;
+        let mut data: OpenAIEmbeddingResponse = response.json().await?;
+        if data.data.len() != input.len() {
+            return Err(anyhow!(
+                "Expect {} embeddings, got {}",
+                input.len(),
+                data.data.len()
+            ));
+        }
+        data.data.sort_by_key(|v| v.index);
+        Ok(data.data.into_iter().map(|v| v.embedding).collect())
+    }
+}
+
 impl AiService for OpenaiAdapter {
     async fn complete(&self, messages: &[Message]) -> anyhow::Result<String> {
         let request = OpenAIChatCompletionRequest {
             model: self.model.clone(),
             messages: messages.iter().map(|m| m.into()).collect(),
+            stream: false,
         };
 
         let url = format!("{}/chat/completions", self.host);
@@ -89,6 +178,40 @@ impl AiService for OpenaiAdapter {
             .content;
         Ok(content)
     }
+
+    async fn complete_stream(&self, messages: &[Message]) -> anyhow::Result<CompletionStream> {
+        let request = OpenAIChatCompletionRequest {
+            model: self.model.clone(),
+            messages: messages.iter().map(|m| m.into()).collect(),
+            stream: true,
+        };
+
+        let url = format!("{}/chat/completions", self.host);
+        let response = self
+            .client
+            .post(url)
+            .json(&request)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // server sent events: `data: {chunk}` lines, terminated by `data: [DONE]`
+        let stream = into_lines(response.bytes_stream())
+            .take_while(|line| future::ready(!matches!(line, Ok(line) if line == "data: [DONE]")))
+            .filter_map(|line| future::ready(parse_chunk(line).transpose()));
+        Ok(stream.boxed())
+    }
+}
+
+fn parse_chunk(line: anyhow::Result<String>) -> anyhow::Result<Option<String>> {
+    let line = line?;
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(None);
+    };
+    let mut chunk: OpenAIChatCompletionChunk = serde_json::from_str(data.trim())?;
+    let content = chunk.choices.pop().and_then(|choice| choice.delta.content);
+    Ok(content.filter(|v| !v.is_empty()))
 }
 
 impl From<OpenaiAdapter> for AiAdapter {
@@ -119,6 +242,7 @@ impl From<&Message> for OpenAIMessage {
 mod tests {
     use super::*;
     use crate::Role;
+    use mock_llm::MockLlm;
     use std::env;
 
     #[ignore]
@@ -133,4 +257,64 @@ mod tests {
         let response = adapter.complete(&messages).await.unwrap();
         assert!(!response.is_empty());
     }
+
+    #[ignore]
+    #[tokio::test]
+    async fn openai_complete_stream_should_work() {
+        let api_key = env::var("OPENAI_API_KEY").unwrap();
+        let adapter = OpenaiAdapter::new(api_key, "gpt-4o");
+        let messages = vec![Message::user("Hello")];
+        let stream = adapter.complete_stream(&messages).await.unwrap();
+        let response: Vec<String> = stream.map(|v| v.unwrap()).collect().await;
+        assert!(!response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn openai_complete_should_work_with_mock_llm() -> anyhow::Result<()> {
+        let server = MockLlm::new().respond("Hi there").serve().await?;
+        let adapter = OpenaiAdapter::new_with_host(server.openai_url(), "sk-test", "gpt-4o");
+        let response = adapter.complete(&[Message::user("Hello")]).await?;
+        assert_eq!(response, "Hi there");
+
+        // no canned response left, the message is echoed back
+        let stream = adapter
+            .complete_stream(&[Message::user("how are you")])
+            .await?;
+        let tokens: Vec<String> = stream.map(|v| v.unwrap()).collect().await;
+        assert_eq!(tokens, ["how ", "are ", "you"]);
+
+        let requests = server.llm.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].stream, Some(true));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn openai_embed_should_work_with_mock_llm() -> anyhow::Result<()> {
+        let server = MockLlm::new().dimensions(8).serve().await?;
+        let adapter = OpenaiAdapter::new_with_host(server.openai_url(), "sk-test", "gpt-4o");
+        let input = ["deploy freeze".to_string(), "lunch menu".to_string()];
+        let vectors = adapter.embed("text-embedding-3-small", &input).await?;
+        assert_eq!(vectors.len(), 2);
+        assert!(vectors.iter().all(|v| v.len() == 8));
+        assert_ne!(vectors[0], vectors[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_chunk_should_work() {
+        let line = r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#;
+        let content = parse_chunk(Ok(line.to_string())).unwrap();
+        assert_eq!(content.as_deref(), Some("Hi"));
+
+        let content = parse_chunk(Ok("".to_string())).unwrap();
+        assert!(content.is_none());
+    }
+
+    #[test]
+    fn parse_chunk_should_work_with_minimal_chunk() {
+        let line = r#"data: {"choices":[{"delta":{"content":"Hi"}}]}"#;
+        let content = parse_chunk(Ok(line.to_string())).unwrap();
+        assert_eq!(content.as_deref(), Some("Hi"));
+    }
 }
@@ -2,8 +2,12 @@ mod adapters;
 
 pub use adapters::*;
 
+use futures::stream::BoxStream;
 use std::fmt;
 
+/// A stream of completion deltas (tokens) as they are generated by the model.
+pub type CompletionStream = BoxStream<'static, anyhow::Result<String>>;
+
 pub enum AiAdapter {
     Openai(OpenaiAdapter),
     Ollama(OllamaAdapter),
@@ -25,6 +29,7 @@ pub struct Message {
 #[allow(async_fn_in_trait)]
 pub trait AiService {
     async fn complete(&self, messages: &[Message]) -> anyhow::Result<String>;
+    async fn complete_stream(&self, messages: &[Message]) -> anyhow::Result<CompletionStream>;
     // other common functions
 }
 
@@ -36,6 +41,13 @@ impl AiService for AiAdapter {
             AiAdapter::Ollama(adapter) => adapter.complete(messages).await,
         }
     }
+
+    async fn complete_stream(&self, messages: &[Message]) -> anyhow::Result<CompletionStream> {
+        match self {
+            AiAdapter::Openai(adapter) => adapter.complete_stream(messages).await,
+            AiAdapter::Ollama(adapter) => adapter.complete_stream(messages).await,
+        }
+    }
 }
 
 impl fmt::Display for Role {
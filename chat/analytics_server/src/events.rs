@@ -55,6 +55,10 @@ pub struct AnalyticsEventRow {
     // NavigationEvent
     pub navigation_from: Option<String>,
     pub navigation_to: Option<String>,
+    // MessageReactedEvent
+    pub reaction_chat_id: Option<String>,
+    pub reaction_emoji: Option<String>,
+    pub reaction_added: Option<bool>,
 }
 
 trait EventConsume {
@@ -188,6 +192,7 @@ impl EventConsume for EventType {
             EventType::ChatJoined(event) => event.consume(row),
             EventType::ChatLeft(event) => event.consume(row),
             EventType::Navigation(event) => event.consume(row),
+            EventType::MessageReacted(event) => event.consume(row),
         }
     }
 }
@@ -274,3 +279,13 @@ impl EventConsume for NavigationEvent {
         Ok(())
     }
 }
+
+impl EventConsume for MessageReactedEvent {
+    fn consume(self, row: &mut AnalyticsEventRow) -> Result<(), AppError> {
+        row.event_type = "message_reacted".to_string();
+        row.reaction_chat_id = Some(self.chat_id);
+        row.reaction_emoji = Some(self.emoji);
+        row.reaction_added = Some(self.added);
+        Ok(())
+    }
+}
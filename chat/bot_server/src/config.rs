@@ -5,6 +5,8 @@ use std::{env, fs::File};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
+    #[serde(default)]
+    pub embedding: EmbeddingConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +15,13 @@ pub struct ServerConfig {
     pub db_url: String,
 }
 
+/// how messages are embedded for semantic search, it must match the model chat_server
+/// embeds search queries with
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    pub model: String,
+}
+
 impl AppConfig {
     pub fn load() -> Result<Self> {
         // read from  ./app.yml, or /etc/config/app.yml, or from env CHAT_CONFIG
@@ -29,3 +38,11 @@ impl AppConfig {
         Ok(ret?)
     }
 }
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            model: "text-embedding-3-small".to_string(),
+        }
+    }
+}
@@ -1,7 +1,9 @@
 mod config;
+mod messages;
 mod notif;
 
 pub use config::AppConfig;
+pub use messages::index_workspace_messages;
 pub use notif::setup_pg_listener;
 
 pub const VECTOR_SIZE: usize = 1536;
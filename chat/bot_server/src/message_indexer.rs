@@ -0,0 +1,29 @@
+use anyhow::Result;
+use bot_server::{index_workspace_messages, AppConfig};
+use sqlx::postgres::PgPoolOptions;
+use swiftide::integrations;
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
+
+// embed chat messages of every workspace for semantic search. Run it periodically,
+// each run only embeds messages created or edited since the last one.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let layer = Layer::new().with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry().with(layer).init();
+
+    let config = AppConfig::load()?;
+    let pool = PgPoolOptions::new().connect(&config.server.db_url).await?;
+    let client = integrations::openai::OpenAI::builder()
+        .default_embed_model(&config.embedding.model)
+        .build()?;
+
+    let workspaces: Vec<(i64,)> = sqlx::query_as("SELECT id FROM workspaces ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+    for (ws_id,) in workspaces {
+        info!("Indexing messages of workspace {}", ws_id);
+        index_workspace_messages(&pool, client.clone(), ws_id).await?;
+    }
+    Ok(())
+}
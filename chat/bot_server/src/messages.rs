@@ -0,0 +1,86 @@
+use crate::VECTOR_SIZE;
+use anyhow::Result;
+use chat_core::{message_embedding_path, message_embeddings_table};
+use sqlx::{FromRow, PgPool};
+use std::path::PathBuf;
+use swiftide::{
+    indexing::{self, transformers::Embed, Node},
+    traits::{EmbeddingModel, Persist},
+};
+use swiftide_pgvector::PgVectorBuilder;
+use tracing::info;
+use uuid::Uuid;
+
+const EMBED_BATCH_SIZE: usize = 10;
+
+#[derive(Debug, FromRow)]
+struct MessageRow {
+    id: i64,
+    chat_id: i64,
+    content: String,
+    modified_content: Option<String>,
+}
+
+/// Embed messages of a workspace into its own pgvector table. It's incremental: only
+/// messages not indexed yet, or edited since they were indexed, are embedded.
+pub async fn index_workspace_messages(
+    pool: &PgPool,
+    embed: impl EmbeddingModel + 'static,
+    ws_id: i64,
+) -> Result<()> {
+    let table = message_embeddings_table(ws_id);
+    let store = PgVectorBuilder::default()
+        .pool(pool.clone())
+        .table_name(table.clone())
+        .vector_size(VECTOR_SIZE as _)
+        .build()?;
+    store.setup().await?;
+
+    let nodes = load_message_nodes(pool, &table, ws_id).await?;
+    if nodes.is_empty() {
+        info!("No new messages in workspace {}", ws_id);
+        return Ok(());
+    }
+    info!("Indexing {} messages in workspace {}", nodes.len(), ws_id);
+
+    indexing::Pipeline::from_stream(nodes)
+        .then_in_batch(Embed::new(embed).with_batch_size(EMBED_BATCH_SIZE))
+        .then_store_with(store)
+        .run()
+        .await?;
+    Ok(())
+}
+
+// one node per message, the path maps a vector back to its message (see
+// `message_embedding_path`) and the id is fixed so re-indexing a message overwrites it
+async fn load_message_nodes(pool: &PgPool, table: &str, ws_id: i64) -> Result<Vec<Node>> {
+    // the store bumps `updated_at` whenever it overwrites a vector
+    let sql = format!(
+        r#"
+        SELECT m.id, m.chat_id, m.content, m.modified_content
+        FROM messages m
+        JOIN chats c ON c.id = m.chat_id
+        LEFT JOIN {table} e ON e.path = 'messages/' || m.chat_id || '/' || m.id
+        WHERE c.ws_id = $1 AND m.deleted_at IS NULL AND m.content != ''
+          AND (e.id IS NULL OR m.edited_at > e.updated_at)
+        ORDER BY m.id
+        "#
+    );
+    let rows: Vec<MessageRow> = sqlx::query_as(&sql).bind(ws_id).fetch_all(pool).await?;
+
+    let nodes = rows
+        .into_iter()
+        .map(|row| {
+            // agents may translate or rewrite a message, both versions are searchable
+            let chunk = match row.modified_content {
+                Some(modified) => format!("{}\n{}", row.content, modified),
+                None => row.content,
+            };
+            let mut node = Node::new(chunk);
+            node.path = PathBuf::from(message_embedding_path(row.chat_id, row.id));
+            node.id = Some(Uuid::from_u64_pair(row.chat_id as _, row.id as _));
+            node
+        })
+        .collect();
+    Ok(nodes)
+}
@@ -0,0 +1,63 @@
+use crate::ChatRead;
+use sqlx::PgPool;
+
+/// Move the read position of a user in a chat forward to a message, or to the latest one
+/// if not given. The position never goes back. Only top level messages count, so `None`
+/// is returned if the message is not a top level message of the chat.
+///
+/// Shared by chat_server and notify_server, `chat_reads` triggers the `ChatRead` event.
+pub async fn mark_chat_read(
+    pool: &PgPool,
+    chat_id: i64,
+    user_id: i64,
+    message_id: Option<i64>,
+) -> Result<Option<ChatRead>, sqlx::Error> {
+    let message_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(max(id), CASE WHEN $2::bigint IS NULL THEN 0 END)
+        FROM messages
+        WHERE chat_id = $1 AND thread_root_id IS NULL AND ($2::bigint IS NULL OR id = $2)
+        "#,
+    )
+    .bind(chat_id)
+    .bind(message_id)
+    .fetch_one(pool)
+    .await?;
+    let Some(message_id) = message_id else {
+        return Ok(None);
+    };
+
+    let read: Option<ChatRead> = sqlx::query_as(
+        r#"
+        INSERT INTO chat_reads (chat_id, user_id, last_read_message_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (chat_id, user_id) DO UPDATE
+        SET last_read_message_id = EXCLUDED.last_read_message_id, updated_at = now()
+        WHERE chat_reads.last_read_message_id < EXCLUDED.last_read_message_id
+        RETURNING chat_id, user_id, last_read_message_id, updated_at
+        "#,
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    // the user has read further already
+    match read {
+        Some(read) => Ok(Some(read)),
+        None => {
+            sqlx::query_as(
+                r#"
+            SELECT chat_id, user_id, last_read_message_id, updated_at
+            FROM chat_reads
+            WHERE chat_id = $1 AND user_id = $2
+            "#,
+            )
+            .bind(chat_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
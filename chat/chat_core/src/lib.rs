@@ -1,13 +1,16 @@
+mod chat_read;
 mod utils;
 
 pub mod middlewares;
 pub mod pb;
 
+pub use chat_read::mark_chat_read;
 pub use utils::*;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::HashMap;
 use thiserror::Error;
 use utoipa::ToSchema;
 
@@ -16,8 +19,18 @@ pub trait Agent {
     async fn process(&self, msg: &str, ctx: &AgentContext) -> Result<AgentDecision, AgentError>;
 }
 
+/// What an agent knows about the conversation besides the message itself.
 #[derive(Debug, Default, Clone)]
-pub struct AgentContext {}
+pub struct AgentContext {
+    /// the user who sent the message
+    pub sender: Option<ChatUser>,
+    /// the chat the message is sent to
+    pub chat: Option<Chat>,
+    /// members of the chat
+    pub members: Vec<ChatUser>,
+    /// recent messages before the current one, oldest first
+    pub history: Vec<Message>,
+}
 
 #[derive(Debug, Clone)]
 pub enum AgentDecision {
@@ -25,6 +38,8 @@ pub enum AgentDecision {
     Reply(String),
     Delete,
     None,
+    /// what a tap agent made of the message, it's recorded but doesn't change the conversation
+    Tap(String),
 }
 
 #[derive(Error, Debug)]
@@ -68,6 +83,10 @@ pub struct ChatUser {
     pub id: i64,
     pub fullname: String,
     pub email: String,
+    /// the user is connected and active, only loaded when listing workspace users
+    #[sqlx(default)]
+    #[serde(default)]
+    pub online: bool,
 }
 
 #[derive(
@@ -87,7 +106,7 @@ pub enum ChatType {
     PublicChannel,
 }
 
-#[derive(Debug, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all(serialize = "camelCase"))]
 pub struct Chat {
     pub id: i64,
@@ -99,6 +118,32 @@ pub struct Chat {
     pub agents: Vec<i64>,
     #[serde(alias = "createdAt")]
     pub created_at: DateTime<Utc>,
+    /// the last message read by the current user, only loaded when listing chats
+    #[sqlx(default)]
+    #[serde(default, alias = "lastReadMessageId")]
+    pub last_read_message_id: Option<i64>,
+    /// number of messages unread by the current user, only loaded when listing chats
+    #[sqlx(default)]
+    #[serde(default, alias = "unreadCount")]
+    pub unread_count: i64,
+    /// ids of the pinned messages, latest pinned first
+    #[sqlx(default)]
+    #[serde(default)]
+    pub pins: Vec<i64>,
+}
+
+/// Read position of a user in a chat
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ChatRead {
+    #[serde(alias = "chatId")]
+    pub chat_id: i64,
+    #[serde(alias = "userId")]
+    pub user_id: i64,
+    #[serde(alias = "lastReadMessageId")]
+    pub last_read_message_id: i64,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
@@ -114,6 +159,38 @@ pub struct Message {
     pub files: Vec<String>,
     #[serde(alias = "createdAt")]
     pub created_at: DateTime<Utc>,
+    /// set when the author edited the message
+    #[serde(default, alias = "editedAt")]
+    pub edited_at: Option<DateTime<Utc>>,
+    /// the message being replied in a thread
+    #[serde(default, alias = "replyTo")]
+    pub reply_to: Option<i64>,
+    /// the top level message of the thread this message is in
+    #[serde(default, alias = "threadRootId")]
+    pub thread_root_id: Option<i64>,
+    /// number of replies in the thread, only for thread root messages
+    #[serde(default, alias = "replyCount")]
+    pub reply_count: i32,
+    /// users mentioned as `@name` in the content
+    #[serde(default)]
+    pub mentions: Vec<i64>,
+    /// agents mentioned as `@name` in the content
+    #[serde(default, alias = "agentMentions")]
+    pub agent_mentions: Vec<i64>,
+    /// aggregated emoji reactions, only loaded when listing messages
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub reactions: Vec<ReactionCount>,
+}
+
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: i64,
+    /// users who reacted with the emoji
+    #[serde(alias = "userIds")]
+    pub user_ids: Vec<i64>,
 }
 
 #[derive(
@@ -155,15 +232,44 @@ pub struct ChatAgent {
     pub name: String,
     pub r#type: AgentType,
     pub adapter: AdapterType,
+    /// provider name in chat_server config, defaults to the one named after the adapter
+    pub provider: Option<String>,
     pub model: String,
+    /// a jinja2 template, rendered with `msg`, `sender`, `chat`, `members`, `history` and `args`
     pub prompt: String,
-    pub args: sqlx::types::Json<serde_json::Value>, // TODO: change to custom type
+    #[schema(value_type = AgentArgs)]
+    pub args: sqlx::types::Json<AgentArgs>,
+    /// the bot user the agent sends its replies as
+    #[serde(alias = "botUserId")]
+    pub bot_user_id: Option<i64>,
     #[serde(alias = "createdAt")]
     pub created_at: DateTime<Utc>,
     #[serde(alias = "updatedAt")]
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentArgs {
+    /// variables available in the prompt template as `args.<name>`
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub vars: HashMap<String, serde_json::Value>,
+    /// how many recent messages the agent sees, defaults to the server config
+    #[serde(
+        default,
+        alias = "history_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub history_size: Option<u64>,
+    /// reply in a thread under the message instead of at top level
+    #[serde(default, alias = "reply_in_thread")]
+    pub reply_in_thread: bool,
+    /// only run when mentioned as `@name` in a message instead of on every message
+    #[serde(default, alias = "on_mention")]
+    pub on_mention: bool,
+}
+
 impl User {
     pub fn new(id: i64, fullname: &str, email: &str) -> Self {
         Self {
@@ -6,7 +6,7 @@ pub struct AnalyticsEvent {
     pub context: ::core::option::Option<EventContext>,
     #[prost(
         oneof = "analytics_event::EventType",
-        tags = "8, 9, 10, 11, 12, 13, 14, 15, 16, 17"
+        tags = "8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18"
     )]
     pub event_type: ::core::option::Option<analytics_event::EventType>,
 }
@@ -34,6 +34,8 @@ pub mod analytics_event {
         ChatLeft(super::ChatLeftEvent),
         #[prost(message, tag = "17")]
         Navigation(super::NavigationEvent),
+        #[prost(message, tag = "18")]
+        MessageReacted(super::MessageReactedEvent),
     }
 }
 /// / 应用启动事件
@@ -148,6 +150,19 @@ pub struct NavigationEvent {
     #[prost(string, tag = "2")]
     pub to: ::prost::alloc::string::String,
 }
+/// / 消息表情回应事件
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageReactedEvent {
+    /// / chat ID
+    #[prost(string, tag = "1")]
+    pub chat_id: ::prost::alloc::string::String,
+    /// / 表情
+    #[prost(string, tag = "2")]
+    pub emoji: ::prost::alloc::string::String,
+    /// / 添加或移除
+    #[prost(bool, tag = "3")]
+    pub added: bool,
+}
 /// / 事件上下文
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct EventContext {
@@ -0,0 +1,22 @@
+/// Message embeddings are stored in one pgvector table per workspace (created by the
+/// indexer), so that a workspace could be re-indexed or dropped on its own.
+pub fn message_embeddings_table(ws_id: i64) -> String {
+    format!("message_embeddings_{ws_id}")
+}
+
+/// The `path` of a message in the embeddings table, it's how a vector is mapped back
+/// to its message.
+pub fn message_embedding_path(chat_id: i64, message_id: i64) -> String {
+    format!("messages/{chat_id}/{message_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_embedding_names_should_work() {
+        assert_eq!(message_embeddings_table(1), "message_embeddings_1");
+        assert_eq!(message_embedding_path(2, 30), "messages/2/30");
+    }
+}
@@ -1,3 +1,5 @@
+mod embedding;
 mod jwt;
 
+pub use embedding::{message_embedding_path, message_embeddings_table};
 pub use jwt::{DecodingKey, EncodingKey};
@@ -1,8 +1,10 @@
-use ai_sdk::{AiAdapter, AiService, OllamaAdapter, OpenaiAdapter};
+use crate::{AppConfig, AppError};
+use ai_sdk::{AiAdapter, AiService, CompletionStream, OllamaAdapter, OpenaiAdapter};
 use chat_core::{
-    AdapterType, Agent, AgentContext, AgentDecision, AgentError, AgentType, ChatAgent,
+    AdapterType, Agent, AgentArgs, AgentContext, AgentDecision, AgentError, AgentType, ChatAgent,
+    ChatType,
 };
-use std::env;
+use minijinja::{context, Environment};
 
 pub enum AgentVariant {
     Proxy(ProxyAgent),
@@ -16,7 +18,7 @@ pub struct ProxyAgent {
     pub name: String,
     pub adapter: AiAdapter,
     pub prompt: String,
-    pub args: serde_json::Value,
+    pub args: AgentArgs,
 }
 
 #[allow(unused)]
@@ -24,7 +26,7 @@ pub struct ReplyAgent {
     pub name: String,
     pub adapter: AiAdapter,
     pub prompt: String,
-    pub args: serde_json::Value,
+    pub args: AgentArgs,
 }
 
 #[allow(unused)]
@@ -32,40 +34,52 @@ pub struct TapAgent {
     pub name: String,
     pub adapter: AiAdapter,
     pub prompt: String,
-    pub args: serde_json::Value,
+    pub args: AgentArgs,
 }
 
 #[allow(unused)]
 pub struct TestAgent;
 
 impl Agent for ProxyAgent {
-    async fn process(&self, msg: &str, _ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
-        // If we need it to be flexible: prompt is a jinja2 template, and args is a json
-        let prompt = format!("{} {}", self.prompt, msg);
-        let messages = vec![ai_sdk::Message::user(prompt)];
+    async fn process(&self, msg: &str, ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
+        let messages = build_messages(&self.prompt, &self.args, msg, ctx)?;
         let res = self.adapter.complete(&messages).await?;
         Ok(AgentDecision::Modify(res))
     }
 }
 
 impl Agent for ReplyAgent {
-    async fn process(&self, msg: &str, _ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
+    async fn process(&self, msg: &str, ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
         // 1. create embedding for the message
         // 2. search related docs via vector db with embedding
         //let docs = searcher.search(msg).await?;
         // 3. query llm with prompt and related docs as context
         // let prompt = format!("{} {} {}", self.prompt, docs, msg);
-        let prompt = format!("{} {}", self.prompt, msg);
-        let messages = vec![ai_sdk::Message::user(prompt)];
+        let messages = build_messages(&self.prompt, &self.args, msg, ctx)?;
         let res = self.adapter.complete(&messages).await?;
         Ok(AgentDecision::Reply(res))
     }
 }
 
-// in future, we should push messages into a queue and process in a delayed manner
+impl ReplyAgent {
+    /// Same as `process`, but yields the reply token by token as the model generates it.
+    pub async fn process_stream(
+        &self,
+        msg: &str,
+        ctx: &AgentContext,
+    ) -> Result<CompletionStream, AgentError> {
+        let messages = build_messages(&self.prompt, &self.args, msg, ctx)?;
+        let stream = self.adapter.complete_stream(&messages).await?;
+        Ok(stream)
+    }
+}
+
+// tap agents are queued in `agent_jobs` and processed by the worker in a delayed manner
 impl Agent for TapAgent {
-    async fn process(&self, _msg: &str, _ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
-        Ok(AgentDecision::None)
+    async fn process(&self, msg: &str, ctx: &AgentContext) -> Result<AgentDecision, AgentError> {
+        let messages = build_messages(&self.prompt, &self.args, msg, ctx)?;
+        let res = self.adapter.complete(&messages).await?;
+        Ok(AgentDecision::Tap(res))
     }
 }
 
@@ -86,40 +100,164 @@ impl Agent for AgentVariant {
     }
 }
 
-impl From<ChatAgent> for AgentVariant {
-    fn from(mut agent: ChatAgent) -> Self {
-        let adapter: AiAdapter = match agent.adapter {
-            AdapterType::Openai => {
-                let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
-                OpenaiAdapter::new(api_key, agent.model).into()
+/// Make sure the agent prompt is a valid template.
+pub(crate) fn validate_prompt(prompt: &str) -> Result<(), minijinja::Error> {
+    Environment::new().template_from_str(prompt)?;
+    Ok(())
+}
+
+// agents should see the content modified by proxy agents
+pub(crate) fn message_content(message: &chat_core::Message) -> &str {
+    message
+        .modified_content
+        .as_deref()
+        .unwrap_or(&message.content)
+}
+
+/// Render the agent prompt (a jinja2 template) with the message, its context and agent args.
+fn render_prompt(
+    prompt: &str,
+    args: &AgentArgs,
+    msg: &str,
+    ctx: &AgentContext,
+    history: &[chat_core::Message],
+) -> Result<String, AgentError> {
+    let ctx = context! {
+        msg => msg,
+        sender => &ctx.sender,
+        chat => &ctx.chat,
+        members => &ctx.members,
+        history => history,
+        args => &args.vars,
+    };
+    Environment::new()
+        .render_str(prompt, ctx)
+        .map_err(|e| anyhow::anyhow!("failed to render prompt: {e}").into())
+}
+
+/// Build a multi-turn conversation for the model: the rendered agent prompt as system
+/// message, then the chat history, then the current message.
+fn build_messages(
+    prompt: &str,
+    args: &AgentArgs,
+    msg: &str,
+    ctx: &AgentContext,
+) -> Result<Vec<ai_sdk::Message>, AgentError> {
+    // an agent may want to see less history than what's in the context
+    let history = match args.history_size {
+        Some(n) => &ctx.history[ctx.history.len().saturating_sub(n as usize)..],
+        None => &ctx.history[..],
+    };
+    let sender_id = ctx.sender.as_ref().map(|u| u.id);
+    let is_single = matches!(&ctx.chat, Some(chat) if chat.r#type == ChatType::Single);
+
+    // in a single chat the other party (usually a bot) is the assistant. In other chats
+    // there are many parties, so we prefix the content with sender's name instead.
+    let to_message = |id: i64, content: &str| {
+        if is_single {
+            if Some(id) == sender_id {
+                ai_sdk::Message::user(content)
+            } else {
+                ai_sdk::Message::assistant(content)
             }
-            AdapterType::Ollama => OllamaAdapter::new_local(agent.model).into(),
-            AdapterType::Test => return AgentVariant::Test(TestAgent),
-        };
+        } else {
+            let name = ctx
+                .members
+                .iter()
+                .find(|u| u.id == id)
+                .map(|u| u.fullname.clone())
+                .unwrap_or_else(|| format!("user {id}"));
+            ai_sdk::Message::user(format!("{name}: {content}"))
+        }
+    };
+
+    let prompt = render_prompt(prompt, args, msg, ctx, history)?;
+    let mut messages = vec![ai_sdk::Message::system(prompt)];
+    messages.extend(
+        history
+            .iter()
+            .map(|m| to_message(m.sender_id, message_content(m))),
+    );
+    messages.push(to_message(sender_id.unwrap_or_default(), msg));
+    Ok(messages)
+}
+
+impl AgentVariant {
+    /// Build a runnable agent, its adapter is created from the provider in config.
+    pub fn try_new(agent: ChatAgent, config: &AppConfig) -> Result<Self, AppError> {
+        if agent.adapter == AdapterType::Test {
+            return Ok(AgentVariant::Test(TestAgent));
+        }
+        let adapter = build_adapter(&agent, config)?;
+        let args = agent.args.0;
 
-        match agent.r#type {
+        let agent = match agent.r#type {
             AgentType::Reply => AgentVariant::Reply(ReplyAgent {
                 name: agent.name,
                 adapter,
                 prompt: agent.prompt,
-                args: agent.args.take(),
+                args,
             }),
             AgentType::Proxy => AgentVariant::Proxy(ProxyAgent {
                 name: agent.name,
                 adapter,
                 prompt: agent.prompt,
-                args: agent.args.take(),
+                args,
             }),
             AgentType::Tap => AgentVariant::Tap(TapAgent {
                 name: agent.name,
                 adapter,
                 prompt: agent.prompt,
-                args: agent.args.take(),
+                args,
             }),
-        }
+        };
+        Ok(agent)
     }
 }
 
+fn build_adapter(agent: &ChatAgent, config: &AppConfig) -> Result<AiAdapter, AppError> {
+    let Some((name, provider)) = config.provider(&agent.adapter, agent.provider.as_deref()) else {
+        return Err(AppError::AdapterError(format!(
+            "provider for agent {} is not configured",
+            agent.name
+        )));
+    };
+    let model = match (agent.model.as_str(), &provider.default_model) {
+        ("", Some(model)) => model.clone(),
+        ("", None) => {
+            return Err(AppError::AdapterError(format!(
+                "agent {} has no model and provider {name} has no default model",
+                agent.name
+            )))
+        }
+        (model, _) => model.to_string(),
+    };
+
+    let adapter = match provider.adapter {
+        AdapterType::Openai => {
+            let Some(api_key) = provider.api_key() else {
+                return Err(AppError::AdapterError(format!(
+                    "api key for provider {name} is not set"
+                )));
+            };
+            match &provider.base_url {
+                Some(url) => OpenaiAdapter::new_with_host(url, api_key, model).into(),
+                None => OpenaiAdapter::new(api_key, model).into(),
+            }
+        }
+        AdapterType::Ollama => match &provider.base_url {
+            Some(url) => OllamaAdapter::new(url, model).into(),
+            None => OllamaAdapter::new_local(model).into(),
+        },
+        AdapterType::Test => {
+            return Err(AppError::AdapterError(format!(
+                "provider {name} can't use the test adapter"
+            )))
+        }
+    };
+    Ok(adapter)
+}
+
 impl From<ProxyAgent> for AgentVariant {
     fn from(agent: ProxyAgent) -> Self {
         AgentVariant::Proxy(agent)
@@ -141,22 +279,212 @@ impl From<TapAgent> for AgentVariant {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::AppState;
+    use crate::config::ProviderConfig;
     use anyhow::Result;
+    use chat_core::{Chat, ChatUser, Message};
+    use futures::StreamExt;
+    use mock_llm::MockLlm;
+
+    #[test]
+    fn build_messages_should_work() -> Result<()> {
+        let alice = ChatUser {
+            id: 1,
+            fullname: "Alice".to_string(),
+            email: "alice@acme.org".to_string(),
+            online: false,
+        };
+        let history = vec![new_message(1, "hi"), new_message(2, "hello, Alice")];
+        let mut ctx = AgentContext {
+            sender: Some(alice.clone()),
+            chat: Some(new_chat(ChatType::Single)),
+            members: vec![alice],
+            history,
+        };
+
+        let args = AgentArgs::default();
+        let messages = build_messages("You are a helpful assistant", &args, "how are you?", &ctx)?;
+        let roles: Vec<_> = messages.iter().map(|m| m.role.to_string()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert_eq!(messages[3].content, "how are you?");
+
+        ctx.chat = Some(new_chat(ChatType::Group));
+        let messages = build_messages("You are a helpful assistant", &args, "how are you?", &ctx)?;
+        let roles: Vec<_> = messages.iter().map(|m| m.role.to_string()).collect();
+        assert_eq!(roles, ["system", "user", "user", "user"]);
+        assert_eq!(messages[2].content, "user 2: hello, Alice");
+        assert_eq!(messages[3].content, "Alice: how are you?");
+
+        // history modified by proxy agents is sent as modified
+        ctx.history[1].modified_content = Some("hola, Alice".to_string());
+        let messages = build_messages("You are a helpful assistant", &args, "how are you?", &ctx)?;
+        assert_eq!(messages[2].content, "user 2: hola, Alice");
+        Ok(())
+    }
+
+    #[test]
+    fn build_messages_should_render_prompt() -> Result<()> {
+        let alice = ChatUser {
+            id: 1,
+            fullname: "Alice".to_string(),
+            email: "alice@acme.org".to_string(),
+            online: false,
+        };
+        let ctx = AgentContext {
+            sender: Some(alice),
+            chat: Some(new_chat(ChatType::Single)),
+            members: vec![],
+            history: vec![new_message(1, "hi"), new_message(2, "hello")],
+        };
+        let args = AgentArgs {
+            vars: [("lang".to_string(), serde_json::json!("English"))].into(),
+            history_size: Some(1),
+            ..Default::default()
+        };
+        let prompt = "Talk to {{ sender.fullname }} in {{ args.lang }}. {{ history | length }} previous message(s).";
+        let messages = build_messages(prompt, &args, "how are you?", &ctx)?;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0].content,
+            "Talk to Alice in English. 1 previous message(s)."
+        );
+        assert_eq!(messages[1].content, "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn validate_prompt_should_work() {
+        assert!(validate_prompt("You are {{ args.role }}").is_ok());
+        assert!(validate_prompt("You are {{ args.role ").is_err());
+    }
+
+    fn new_chat(r#type: ChatType) -> Chat {
+        Chat {
+            id: 1,
+            ws_id: 1,
+            name: None,
+            r#type,
+            members: vec![1, 2],
+            agents: vec![],
+            created_at: chrono::Utc::now(),
+            last_read_message_id: None,
+            unread_count: 0,
+            pins: vec![],
+        }
+    }
+
+    fn new_message(sender_id: i64, content: &str) -> Message {
+        Message {
+            id: 0,
+            chat_id: 1,
+            sender_id,
+            content: content.to_string(),
+            modified_content: None,
+            files: vec![],
+            created_at: chrono::Utc::now(),
+            edited_at: None,
+            reply_to: None,
+            thread_root_id: None,
+            reply_count: 0,
+            mentions: vec![],
+            agent_mentions: vec![],
+            reactions: vec![],
+        }
+    }
 
-    #[ignore]
     #[tokio::test]
     async fn agent_variant_should_work() -> Result<()> {
-        let (_tdb, state) = AppState::new_for_test().await?;
-        let agents = state.list_agents(1).await.expect("list agents failed");
-        let agent = agents[0].clone();
-        let agent: AgentVariant = agent.into();
+        let server = MockLlm::new()
+            .when("hello", "你好")
+            .respond("It is sunny today")
+            .serve()
+            .await?;
+        let mut config = AppConfig::load()?;
+        config.providers.insert(
+            "mock".to_string(),
+            ProviderConfig {
+                adapter: AdapterType::Openai,
+                base_url: Some(server.openai_url()),
+                api_key: Some("sk-test".to_string()),
+                api_key_env: None,
+                default_model: None,
+            },
+        );
+
+        let mut agent = new_agent(AgentType::Proxy);
+        agent.provider = Some("mock".to_string());
+        let agent = AgentVariant::try_new(agent, &config)?;
         let decision = agent.process("hello", &AgentContext::default()).await?;
-        // test if it is modify
-        if let AgentDecision::Modify(_content) = decision {
-        } else {
-            panic!("decision is not modify");
-        }
+        assert!(matches!(decision, AgentDecision::Modify(s) if s == "你好"));
+
+        let mut agent = new_agent(AgentType::Reply);
+        agent.provider = Some("mock".to_string());
+        let AgentVariant::Reply(agent) = AgentVariant::try_new(agent, &config)? else {
+            panic!("agent is not a reply agent");
+        };
+        let stream = agent
+            .process_stream("how is the weather?", &AgentContext::default())
+            .await?;
+        let tokens: Vec<_> = stream.map(|v| v.unwrap()).collect().await;
+        assert_eq!(tokens.concat(), "It is sunny today");
+
+        // the rendered prompt is sent as system message
+        let requests = server.llm.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].messages[0].role, "system");
+        assert_eq!(
+            requests[1].messages[0].content,
+            "You are a helpful assistant"
+        );
         Ok(())
     }
+
+    #[test]
+    fn agent_variant_should_use_configured_provider() -> Result<()> {
+        let mut config = AppConfig::load()?;
+        config.providers.insert(
+            "gateway".to_string(),
+            ProviderConfig {
+                adapter: AdapterType::Openai,
+                base_url: Some("http://localhost:8080/v1".to_string()),
+                api_key: Some("sk-test".to_string()),
+                api_key_env: None,
+                default_model: Some("gpt-4o-mini".to_string()),
+            },
+        );
+        let mut agent = new_agent(AgentType::Reply);
+        agent.provider = Some("gateway".to_string());
+        let agent = AgentVariant::try_new(agent, &config)?;
+        assert!(matches!(agent, AgentVariant::Reply(_)));
+
+        // unknown provider is an error rather than a panic
+        let mut agent = new_agent(AgentType::Proxy);
+        agent.provider = Some("unknown".to_string());
+        let ret = AgentVariant::try_new(agent, &config);
+        assert!(matches!(ret, Err(AppError::AdapterError(_))));
+
+        // model falls back to provider's default model
+        let mut agent = new_agent(AgentType::Tap);
+        agent.provider = Some("gateway".to_string());
+        agent.model = "".to_string();
+        let ret = build_adapter(&agent, &config)?;
+        assert!(matches!(ret, AiAdapter::Openai(_)));
+        Ok(())
+    }
+
+    fn new_agent(r#type: AgentType) -> ChatAgent {
+        ChatAgent {
+            id: 1,
+            chat_id: 1,
+            name: "test".to_string(),
+            r#type,
+            adapter: AdapterType::Openai,
+            provider: None,
+            model: "gpt-4o".to_string(),
+            prompt: "You are a helpful assistant".to_string(),
+            args: sqlx::types::Json(AgentArgs::default()),
+            bot_user_id: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
 }
@@ -1,12 +1,55 @@
-use std::{env, fs::File, path::PathBuf};
+use std::{collections::HashMap, env, fs::File, path::PathBuf, time::Duration};
 
 use anyhow::{bail, Result};
+use chat_core::AdapterType;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub worker: WorkerConfig,
+    #[serde(default)]
+    pub agent: AgentConfig,
+    /// named LLM providers agents could use, keyed by provider name
+    #[serde(default = "default_providers")]
+    pub providers: HashMap<String, ProviderConfig>,
+    #[serde(default)]
+    pub embedding: EmbeddingConfig,
+}
+
+/// how search queries are embedded for semantic search, it must match the model the
+/// message indexer (bot_server) uses
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    /// name of an OpenAI compatible provider
+    pub provider: String,
+    pub model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentConfig {
+    /// number of recent messages sent to agents as conversation history
+    pub history_size: u64,
+}
+
+/// an LLM provider, e.g. OpenAI, a local ollama or a self-hosted OpenAI compatible gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// the API the provider speaks
+    pub adapter: AdapterType,
+    /// use the adapter's default host if not set
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// read the api key from this env var if `api_key` is not set
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// model used by agents which don't specify one
+    #[serde(default)]
+    pub default_model: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,7 +65,107 @@ pub struct ServerConfig {
     pub base_dir: PathBuf,
 }
 
+/// background worker that runs queued agent jobs (tap agents)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkerConfig {
+    /// number of jobs processed concurrently
+    pub concurrency: usize,
+    /// how long to wait (in ms) before polling again when the queue is empty
+    pub poll_interval: u64,
+    /// a job is marked as dead after this many failed attempts
+    pub max_attempts: i32,
+    /// first retry delay (in ms), doubled on every failed attempt
+    pub backoff: u64,
+    /// a job not finished in this time (in seconds) fails, a running job not updated for this
+    /// long is considered stuck (e.g. the worker crashed) and retried
+    pub timeout: u64,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 2,
+            poll_interval: 1000,
+            max_attempts: 5,
+            backoff: 1000,
+            timeout: 300,
+        }
+    }
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            provider: "openai".to_string(),
+            model: "text-embedding-3-small".to_string(),
+        }
+    }
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self { history_size: 10 }
+    }
+}
+
+impl ProviderConfig {
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key.clone().or_else(|| {
+            self.api_key_env
+                .as_ref()
+                .and_then(|name| env::var(name).ok())
+        })
+    }
+}
+
+// the providers available when none are configured
+fn default_providers() -> HashMap<String, ProviderConfig> {
+    let openai = ProviderConfig {
+        adapter: AdapterType::Openai,
+        base_url: None,
+        api_key: None,
+        api_key_env: Some("OPENAI_API_KEY".to_string()),
+        default_model: Some("gpt-4o".to_string()),
+    };
+    let ollama = ProviderConfig {
+        adapter: AdapterType::Ollama,
+        base_url: None,
+        api_key: None,
+        api_key_env: None,
+        default_model: Some("llama3.2".to_string()),
+    };
+    HashMap::from([
+        ("openai".to_string(), openai),
+        ("ollama".to_string(), ollama),
+    ])
+}
+
+impl WorkerConfig {
+    /// retry delay after the given number of failed attempts
+    pub fn backoff_delay(&self, attempts: i32) -> Duration {
+        let exp = attempts.clamp(1, 16) as u32 - 1;
+        Duration::from_millis(self.backoff.saturating_mul(1 << exp))
+    }
+}
+
 impl AppConfig {
+    /// Find the provider an agent uses. Agents without a provider use the one named
+    /// after their adapter, e.g. `openai`.
+    pub fn provider(
+        &self,
+        adapter: &AdapterType,
+        name: Option<&str>,
+    ) -> Option<(&str, &ProviderConfig)> {
+        let name = name.unwrap_or(match adapter {
+            AdapterType::Openai => "openai",
+            AdapterType::Ollama => "ollama",
+            AdapterType::Test => "test",
+        });
+        self.providers
+            .get_key_value(name)
+            .map(|(name, provider)| (name.as_str(), provider))
+    }
+
     pub fn load() -> Result<Self> {
         // read from  ./app.yml, or /etc/config/app.yml, or from env CHAT_CONFIG
         let ret = match (
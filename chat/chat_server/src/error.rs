@@ -19,6 +19,9 @@ pub enum AppError {
     #[error("create chat error: {0}")]
     CreateChatError(String),
 
+    #[error("update chat error: {0}")]
+    UpdateChatError(String),
+
     #[error("create agent error: {0}")]
     CreateAgentError(String),
 
@@ -28,9 +31,24 @@ pub enum AppError {
     #[error("user {user_id} is not member of chat {chat_id}")]
     NotChatMemberError { user_id: u64, chat_id: u64 },
 
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+
     #[error("create message error: {0}")]
     CreateMessageError(String),
 
+    #[error("update message error: {0}")]
+    UpdateMessageError(String),
+
+    #[error("list messages error: {0}")]
+    ListMessagesError(String),
+
+    #[error("reaction error: {0}")]
+    ReactionError(String),
+
+    #[error("search error: {0}")]
+    SearchError(String),
+
     #[error("{0}")]
     ChatFileError(String),
 
@@ -55,6 +73,9 @@ pub enum AppError {
     #[error("http header parse error: {0}")]
     HttpHeaderError(#[from] axum::http::header::InvalidHeaderValue),
 
+    #[error("agent adapter error: {0}")]
+    AdapterError(String),
+
     #[error("ai agent error: {0}")]
     AiAgentError(#[from] AgentError),
 }
@@ -76,14 +97,21 @@ impl IntoResponse for AppError {
             Self::HttpHeaderError(_) => StatusCode::UNPROCESSABLE_ENTITY,
             Self::EmailAlreadyExists(_) => StatusCode::CONFLICT,
             Self::CreateChatError(_) => StatusCode::BAD_REQUEST,
+            Self::UpdateChatError(_) => StatusCode::BAD_REQUEST,
             Self::CreateAgentError(_) => StatusCode::BAD_REQUEST,
             Self::UpdateAgentError(_) => StatusCode::BAD_REQUEST,
             Self::NotChatMemberError { .. } => StatusCode::FORBIDDEN,
+            Self::PermissionDenied(_) => StatusCode::FORBIDDEN,
             Self::NotLoggedIn => StatusCode::UNAUTHORIZED,
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::CreateMessageError(_) => StatusCode::BAD_REQUEST,
+            Self::UpdateMessageError(_) => StatusCode::BAD_REQUEST,
+            Self::ListMessagesError(_) => StatusCode::BAD_REQUEST,
+            Self::ReactionError(_) => StatusCode::BAD_REQUEST,
+            Self::SearchError(_) => StatusCode::BAD_REQUEST,
             Self::ChatFileError(_) => StatusCode::BAD_REQUEST,
+            Self::AdapterError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::AiAgentError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -51,6 +51,33 @@ pub(crate) async fn create_agent_handler(
     Ok((StatusCode::CREATED, Json(agent)))
 }
 
+/// Get the agent by id.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/agents/{agent_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("agent_id" = u64, Path, description = "Agent id")
+    ),
+    responses(
+        (status = 200, description = "Agent found", body = ChatAgent),
+        (status = 404, description = "Agent not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn get_agent_handler(
+    Path((id, agent_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let agent = state.get_agent(id, agent_id).await?;
+    match agent {
+        Some(agent) => Ok(Json(agent)),
+        None => Err(AppError::NotFound(format!("agent id {agent_id}"))),
+    }
+}
+
 /// Update the agent by id.
 #[utoipa::path(
     patch,
@@ -60,18 +87,42 @@ pub(crate) async fn create_agent_handler(
         ("agent_id" = u64, Path, description = "Agent id")
     ),
     responses(
-        (status = 200, description = "Chat found", body = Chat),
-        (status = 404, description = "Chat not found", body = ErrorOutput),
+        (status = 200, description = "Agent updated", body = ChatAgent),
+        (status = 404, description = "Agent not found", body = ErrorOutput),
     ),
     security(
         ("token" = [])
     )
 )]
 pub(crate) async fn update_agent_handler(
-    Path(id): Path<u64>,
+    Path((id, agent_id)): Path<(u64, u64)>,
     State(state): State<AppState>,
     Json(input): Json<UpdateAgent>,
 ) -> Result<impl IntoResponse, AppError> {
-    let agent = state.update_agent(input, id as _).await?;
+    let agent = state.update_agent(id, agent_id, input).await?;
     Ok((StatusCode::OK, Json(agent)))
 }
+
+/// Delete the agent by id.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/agents/{agent_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("agent_id" = u64, Path, description = "Agent id")
+    ),
+    responses(
+        (status = 204, description = "Agent deleted"),
+        (status = 404, description = "Agent not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn delete_agent_handler(
+    Path((id, agent_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_agent(id, agent_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
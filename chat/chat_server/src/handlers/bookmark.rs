@@ -0,0 +1,75 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState};
+use chat_core::User;
+
+/// List bookmarked messages of the user across all the chats, latest first.
+#[utoipa::path(
+    get,
+    path = "/api/bookmarks",
+    responses(
+        (status = 200, description = "Bookmarked messages", body = Vec<Bookmark>),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_bookmarks_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let bookmarks = state.list_bookmarks(user.id as _).await?;
+    Ok(Json(bookmarks))
+}
+
+/// Bookmark a message, bookmarks are private to the user.
+#[utoipa::path(
+    put,
+    path = "/api/bookmarks/{msg_id}",
+    params(
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 204, description = "Message bookmarked"),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn add_bookmark_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(msg_id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.add_bookmark(msg_id, user.id as _).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a bookmark.
+#[utoipa::path(
+    delete,
+    path = "/api/bookmarks/{msg_id}",
+    params(
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 204, description = "Bookmark removed"),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn remove_bookmark_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(msg_id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.remove_bookmark(msg_id, user.id as _).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,4 +1,4 @@
-use crate::{AppError, AppState, CreateChat};
+use crate::{AppError, AppState, CreateChat, MarkRead, UpdateChat};
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -74,12 +74,80 @@ pub(crate) async fn get_chat_handler(
     }
 }
 
-// TODO: finish this as a homework
-pub(crate) async fn update_chat_handler() -> impl IntoResponse {
-    "update chat"
+/// Update the chat name, members or visibility. Members and visibility could only be changed
+/// by the chat creator or workspace owner.
+#[utoipa::path(
+    patch,
+    path = "/api/chats/{id}",
+    params(
+        ("id" = u64, Path, description = "Chat id")
+    ),
+    responses(
+        (status = 200, description = "Chat updated", body = Chat),
+        (status = 400, description = "Invalid input", body = ErrorOutput),
+        (status = 403, description = "Permission denied", body = ErrorOutput),
+        (status = 404, description = "Chat not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn update_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<UpdateChat>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.update_chat(id, input, user.id as _).await?;
+    Ok((StatusCode::OK, Json(chat)))
+}
+
+/// Delete the chat by id. Only the chat creator or workspace owner could delete it.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}",
+    params(
+        ("id" = u64, Path, description = "Chat id")
+    ),
+    responses(
+        (status = 204, description = "Chat deleted"),
+        (status = 403, description = "Permission denied", body = ErrorOutput),
+        (status = 404, description = "Chat not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn delete_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_chat(id, user.id as _).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-// TODO: finish this as a homework
-pub(crate) async fn delete_chat_handler() -> impl IntoResponse {
-    "delete chat"
+/// Mark messages in the chat as read by the user, up to the given message.
+#[utoipa::path(
+    post,
+    path = "/api/chats/{id}/read",
+    params(
+        ("id" = u64, Path, description = "Chat id")
+    ),
+    responses(
+        (status = 200, description = "Read position of the user", body = ChatRead),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn mark_chat_read_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<MarkRead>,
+) -> Result<impl IntoResponse, AppError> {
+    let read = state.mark_chat_read(id, user.id as _, input).await?;
+    Ok(Json(read))
 }
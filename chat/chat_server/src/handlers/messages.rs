@@ -4,13 +4,15 @@ use axum::{
     response::IntoResponse,
     Extension, Json,
 };
+use chrono::Utc;
 use tokio::fs;
 use tracing::{info, warn};
 
-use crate::{AppError, AppState, ChatFile, CreateMessage, ListMessages};
+use crate::{AppError, AppState, ChatFile, CreateMessage, EditMessage, ListMessages};
 use chat_core::User;
 
-/// Send a new message in the chat.
+/// Send a new message in the chat. With a future `send_at`, the message is scheduled
+/// to be sent then.
 #[utoipa::path(
     post,
     path = "/api/chats/{id}",
@@ -18,7 +20,8 @@ use chat_core::User;
         ("id" = u64, Path, description = "Chat id")
     ),
     responses(
-        (status = 200, description = "List of messages", body = Message),
+        (status = 201, description = "Message sent", body = Message),
+        (status = 202, description = "Message scheduled", body = ScheduledMessage),
         (status = 400, description = "Invalid input", body = ErrorOutput),
     ),
     security(
@@ -31,12 +34,16 @@ pub(crate) async fn send_message_handler(
     Path(id): Path<u64>,
     Json(input): Json<CreateMessage>,
 ) -> Result<impl IntoResponse, AppError> {
+    if input.send_at.is_some_and(|t| t > Utc::now()) {
+        let scheduled = state.schedule_message(input, id, user.id as _).await?;
+        return Ok((StatusCode::ACCEPTED, Json(scheduled)).into_response());
+    }
     let msg = state.create_message(input, id, user.id as _).await?;
 
-    Ok((StatusCode::CREATED, Json(msg)))
+    Ok((StatusCode::CREATED, Json(msg)).into_response())
 }
 
-/// List all messages in the chat.
+/// List messages in the chat a page at a time, latest first.
 #[utoipa::path(
     get,
     path = "/api/chats/{id}/messages",
@@ -46,7 +53,7 @@ pub(crate) async fn send_message_handler(
 
     ),
     responses(
-        (status = 200, description = "List of messages", body = Vec<Message>),
+        (status = 200, description = "A page of messages", body = MessagePage),
         (status = 400, description = "Invalid input", body = ErrorOutput),
     ),
     security(
@@ -58,10 +65,110 @@ pub(crate) async fn list_message_handler(
     Path(id): Path<u64>,
     Query(input): Query<ListMessages>,
 ) -> Result<impl IntoResponse, AppError> {
-    let messages = state.list_messages(input, id).await?;
+    let page = state.list_messages(input, id).await?;
+    Ok(Json(page))
+}
+
+/// Edit a message, only its author could do it.
+#[utoipa::path(
+    patch,
+    path = "/api/chats/{id}/messages/{msg_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 200, description = "Message edited", body = Message),
+        (status = 403, description = "Not the author", body = ErrorOutput),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn edit_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+    Json(input): Json<EditMessage>,
+) -> Result<impl IntoResponse, AppError> {
+    let msg = state.edit_message(id, msg_id, input, user.id as _).await?;
+    Ok(Json(msg))
+}
+
+/// Delete a message, only its author could do it.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/messages/{msg_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 204, description = "Message deleted"),
+        (status = 403, description = "Not the author", body = ErrorOutput),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn delete_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_message(id, msg_id, user.id as _).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the thread of a message: the root message followed by its replies.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/messages/{msg_id}/thread",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Id of the root or any reply in the thread")
+    ),
+    responses(
+        (status = 200, description = "Messages in the thread, oldest first", body = Vec<Message>),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_thread_handler(
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let messages = state.list_thread_messages(id, msg_id).await?;
     Ok(Json(messages))
 }
 
+/// List previous versions of an edited message.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/messages/{msg_id}/edits",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 200, description = "Previous versions, oldest first", body = Vec<MessageEdit>),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_message_edits_handler(
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let edits = state.list_message_edits(id, msg_id).await?;
+    Ok(Json(edits))
+}
+
 pub(crate) async fn file_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
@@ -1,15 +1,25 @@
 mod agent;
 mod auth;
+mod bookmark;
 mod chat;
 mod messages;
+mod pin;
+mod reaction;
+mod scheduled;
+mod search;
 mod workspace;
 
 use axum::response::IntoResponse;
 
 pub(crate) use agent::*;
 pub(crate) use auth::*;
+pub(crate) use bookmark::*;
 pub(crate) use chat::*;
 pub(crate) use messages::*;
+pub(crate) use pin::*;
+pub(crate) use reaction::*;
+pub(crate) use scheduled::*;
+pub(crate) use search::*;
 pub(crate) use workspace::*;
 
 pub(crate) async fn index_handler() -> impl IntoResponse {
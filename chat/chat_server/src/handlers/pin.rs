@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState};
+use chat_core::User;
+
+/// List pinned messages of the chat, latest pinned first.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/pins",
+    params(
+        ("id" = u64, Path, description = "Chat id")
+    ),
+    responses(
+        (status = 200, description = "Pinned messages", body = Vec<PinnedMessage>),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_pins_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let pins = state.list_pins(id).await?;
+    Ok(Json(pins))
+}
+
+/// Pin a message in the chat.
+#[utoipa::path(
+    put,
+    path = "/api/chats/{id}/pins/{msg_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 200, description = "Pinned messages", body = Vec<PinnedMessage>),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn pin_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pins = state.pin_message(id, msg_id, user.id as _).await?;
+    Ok(Json(pins))
+}
+
+/// Unpin a message in the chat.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/pins/{msg_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id")
+    ),
+    responses(
+        (status = 200, description = "Pinned messages", body = Vec<PinnedMessage>),
+        (status = 403, description = "Not allowed to unpin the message", body = ErrorOutput),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn unpin_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let pins = state.unpin_message(id, msg_id, user.id as _).await?;
+    Ok(Json(pins))
+}
@@ -0,0 +1,63 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState};
+use chat_core::User;
+
+/// React to a message with an emoji.
+#[utoipa::path(
+    put,
+    path = "/api/chats/{id}/messages/{msg_id}/reactions/{emoji}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id"),
+        ("emoji" = String, Path, description = "Emoji of the reaction")
+    ),
+    responses(
+        (status = 200, description = "Reactions of the message", body = Vec<ReactionCount>),
+        (status = 400, description = "Invalid emoji", body = ErrorOutput),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn add_reaction_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id, emoji)): Path<(u64, u64, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let reactions = state.add_reaction(id, msg_id, user.id as _, &emoji).await?;
+    Ok(Json(reactions))
+}
+
+/// Remove the user's emoji reaction from a message.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/messages/{msg_id}/reactions/{emoji}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("msg_id" = u64, Path, description = "Message id"),
+        ("emoji" = String, Path, description = "Emoji of the reaction")
+    ),
+    responses(
+        (status = 200, description = "Reactions of the message", body = Vec<ReactionCount>),
+        (status = 404, description = "Message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn remove_reaction_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id, emoji)): Path<(u64, u64, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let reactions = state
+        .remove_reaction(id, msg_id, user.id as _, &emoji)
+        .await?;
+    Ok(Json(reactions))
+}
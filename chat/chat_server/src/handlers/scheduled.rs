@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState};
+use chat_core::User;
+
+/// List the user's messages scheduled in the chat which are not sent yet.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/scheduled",
+    params(
+        ("id" = u64, Path, description = "Chat id")
+    ),
+    responses(
+        (status = 200, description = "Pending scheduled messages", body = Vec<ScheduledMessage>),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_scheduled_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let scheduled = state.list_scheduled_messages(id, user.id as _).await?;
+    Ok(Json(scheduled))
+}
+
+/// Cancel a scheduled message which is not sent yet.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/scheduled/{scheduled_id}",
+    params(
+        ("id" = u64, Path, description = "Chat id"),
+        ("scheduled_id" = u64, Path, description = "Scheduled message id")
+    ),
+    responses(
+        (status = 200, description = "Scheduled message cancelled", body = ScheduledMessage),
+        (status = 404, description = "Scheduled message not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn cancel_scheduled_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, scheduled_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let scheduled = state
+        .cancel_scheduled_message(id, scheduled_id, user.id as _)
+        .await?;
+    Ok(Json(scheduled))
+}
@@ -0,0 +1,60 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState, FindSimilar, SearchMessages};
+use chat_core::User;
+
+/// Search messages in the chats of the user.
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(
+        SearchMessages
+    ),
+    responses(
+        (status = 200, description = "Matched messages, best matches first", body = Vec<SearchHit>),
+        (status = 400, description = "Invalid query", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn search_messages_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<SearchMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let hits = state
+        .search_messages(input, user.id as _, user.ws_id as _)
+        .await?;
+    Ok(Json(hits))
+}
+
+/// Find messages similar in meaning to the query, in the chats of the user.
+#[utoipa::path(
+    get,
+    path = "/api/search/similar",
+    params(
+        FindSimilar
+    ),
+    responses(
+        (status = 200, description = "Similar messages, closest first", body = Vec<SimilarHit>),
+        (status = 400, description = "Invalid query", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn find_similar_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<FindSimilar>,
+) -> Result<impl IntoResponse, AppError> {
+    let hits = state
+        .find_similar_messages(input, user.id as _, user.ws_id as _)
+        .await?;
+    Ok(Json(hits))
+}
@@ -5,6 +5,8 @@ mod handlers;
 mod middlewares;
 mod models;
 mod openapi;
+mod scheduler;
+mod worker;
 
 use anyhow::Context;
 use chat_core::{
@@ -25,11 +27,13 @@ pub use models::*;
 use axum::{
     http::Method,
     middleware::from_fn_with_state,
-    routing::{get, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 
-pub use config::AppConfig;
+pub use config::{AppConfig, EmbeddingConfig, ProviderConfig};
+pub use scheduler::spawn_message_scheduler;
+pub use worker::spawn_agent_workers;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
@@ -55,11 +59,39 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
         )
         .route(
             "/:id/agents",
-            get(list_agent_handler)
-                .post(create_agent_handler)
-                .patch(update_agent_handler),
+            get(list_agent_handler).post(create_agent_handler),
         )
+        .route(
+            "/:id/agents/:agent_id",
+            get(get_agent_handler)
+                .patch(update_agent_handler)
+                .delete(delete_agent_handler),
+        )
+        .route("/:id/read", post(mark_chat_read_handler))
         .route("/:id/messages", get(list_message_handler))
+        .route(
+            "/:id/messages/:msg_id",
+            patch(edit_message_handler).delete(delete_message_handler),
+        )
+        .route("/:id/messages/:msg_id/thread", get(list_thread_handler))
+        .route(
+            "/:id/messages/:msg_id/edits",
+            get(list_message_edits_handler),
+        )
+        .route(
+            "/:id/messages/:msg_id/reactions/:emoji",
+            put(add_reaction_handler).delete(remove_reaction_handler),
+        )
+        .route("/:id/scheduled", get(list_scheduled_handler))
+        .route(
+            "/:id/scheduled/:scheduled_id",
+            delete(cancel_scheduled_handler),
+        )
+        .route("/:id/pins", get(list_pins_handler))
+        .route(
+            "/:id/pins/:msg_id",
+            put(pin_message_handler).delete(unpin_message_handler),
+        )
         .layer(from_fn_with_state(state.clone(), verify_chat))
         .route("/", get(list_chat_handler).post(create_chat_handler));
 
@@ -77,6 +109,13 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
     let api = Router::new()
         .route("/users", get(list_chat_users_handler))
         .nest("/chats", chat)
+        .route("/search", get(search_messages_handler))
+        .route("/search/similar", get(find_similar_handler))
+        .route("/bookmarks", get(list_bookmarks_handler))
+        .route(
+            "/bookmarks/:msg_id",
+            put(add_bookmark_handler).delete(remove_bookmark_handler),
+        )
         .route("/upload", post(upload_handler))
         .route("/files/:ws_id/*path", get(file_handler))
         .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
@@ -118,6 +157,7 @@ impl AppState {
             .context("create base_dir failed")?;
         let dk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
         let ek = EncodingKey::load(&config.auth.sk).context("load sk failed")?;
+        build_embedding_adapter(&config)?;
         let pool = PgPool::connect(&config.server.db_url)
             .await
             .context("connect to db failed")?;
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use chat_server::{get_router, AppConfig, AppState};
+use chat_server::{get_router, spawn_agent_workers, spawn_message_scheduler, AppConfig, AppState};
 use tokio::net::TcpListener;
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
@@ -13,6 +13,8 @@ async fn main() -> Result<()> {
     let addr = format!("0.0.0.0:{}", config.server.port);
 
     let state = AppState::try_new(config).await?;
+    spawn_agent_workers(state.clone());
+    spawn_message_scheduler(state.clone());
     let app = get_router(state).await?;
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
@@ -5,13 +5,20 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use chat_core::User;
+use std::collections::HashMap;
 use tracing::warn;
 
 pub async fn verify_chat(State(state): State<AppState>, req: Request, next: Next) -> Response {
     let (mut parts, body) = req.into_parts();
-    let Path(chat_id) = Path::<u64>::from_request_parts(&mut parts, &state)
+    // nested routes (e.g. /:id/agents/:agent_id) have more than one path param, and
+    // not all of them are numbers (e.g. the emoji of a reaction)
+    let Path(params) = Path::<HashMap<String, String>>::from_request_parts(&mut parts, &state)
         .await
         .unwrap();
+    let Some(chat_id) = params.get("id").and_then(|id| id.parse::<u64>().ok()) else {
+        warn!("chat id not found in path");
+        return AppError::NotFound("chat id".to_string()).into_response();
+    };
 
     let Some(user) = parts.extensions.get::<User>() else {
         warn!("user not found in request");
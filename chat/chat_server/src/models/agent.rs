@@ -1,5 +1,5 @@
-use crate::{AppError, AppState};
-use chat_core::{AdapterType, AgentType, ChatAgent};
+use crate::{agent::validate_prompt, AppError, AppState};
+use chat_core::{AdapterType, AgentArgs, AgentType, ChatAgent};
 use serde::{Deserialize, Serialize};
 use tracing::info;
 use utoipa::ToSchema;
@@ -10,24 +10,28 @@ pub struct CreateAgent {
     pub name: String,
     pub r#type: AgentType,
     pub adapter: AdapterType,
+    /// provider name in config, defaults to the one named after the adapter
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// use provider's default model if empty
+    #[serde(default)]
     pub model: String,
     pub prompt: String,
-    #[serde(default = "default_map")]
-    pub args: serde_json::Value,
-}
-
-fn default_map() -> serde_json::Value {
-    serde_json::Value::Object(serde_json::Map::new())
+    #[serde(default)]
+    pub args: AgentArgs,
 }
 
+/// Fields to change in an agent, missing fields are left untouched.
 #[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "camelCase"))]
 pub struct UpdateAgent {
-    pub id: u64,
-    #[serde(default)]
-    pub prompt: String,
-    #[serde(default)]
-    pub args: serde_json::Value,
+    pub name: Option<String>,
+    pub r#type: Option<AgentType>,
+    pub adapter: Option<AdapterType>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub prompt: Option<String>,
+    pub args: Option<AgentArgs>,
 }
 
 impl CreateAgent {
@@ -37,27 +41,25 @@ impl CreateAgent {
         adapter: AdapterType,
         model: impl Into<String>,
         prompt: impl Into<String>,
-        args: impl Serialize,
+        args: AgentArgs,
     ) -> Self {
         Self {
             name: name.into(),
             r#type,
             adapter,
+            provider: None,
             model: model.into(),
             prompt: prompt.into(),
-            args: serde_json::to_value(args).unwrap(),
+            args,
         }
     }
 }
 
-impl UpdateAgent {
-    pub fn new(id: u64, prompt: impl Into<String>, args: impl Serialize) -> Self {
-        Self {
-            id,
-            prompt: prompt.into(),
-            args: serde_json::to_value(args).unwrap(),
-        }
-    }
+/// bot users of agents are under the reserved `.invalid` domain, so no one can sign up with it
+pub(crate) const AGENT_EMAIL_DOMAIN: &str = "@agents.invalid";
+
+fn agent_bot_email(agent_id: i64) -> String {
+    format!("agent-{agent_id}{AGENT_EMAIL_DOMAIN}")
 }
 
 #[allow(dead_code)]
@@ -78,23 +80,54 @@ impl AppState {
         }
 
         // TODO: check if model is supported by adapter
+        self.validate_provider(&input.adapter, input.provider.as_deref(), &input.model)
+            .map_err(AppError::CreateAgentError)?;
 
-        let agent = sqlx::query_as(
+        if let Err(e) = validate_prompt(&input.prompt) {
+            return Err(AppError::CreateAgentError(format!(
+                "Invalid prompt template: {e}"
+            )));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let (agent_id,): (i64,) = sqlx::query_as(
             r#"
-            INSERT INTO chat_agents (chat_id, name, type, adapter, model, prompt, args)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING *
+            INSERT INTO chat_agents (chat_id, name, type, adapter, provider, model, prompt, args)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id
             "#,
         )
         .bind(chat_id as i64)
-        .bind(input.name)
+        .bind(&input.name)
         .bind(input.r#type)
         .bind(input.adapter)
+        .bind(input.provider)
         .bind(input.model)
         .bind(input.prompt)
-        .bind(input.args)
-        .fetch_one(&self.pool)
+        .bind(sqlx::types::Json(input.args))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // the agent replies as its own bot user, which can't sign in with an empty password hash
+        let agent = sqlx::query_as(
+            r#"
+            WITH bot AS (
+                INSERT INTO users (ws_id, fullname, email, password_hash, is_bot)
+                SELECT ws_id, left($2, 64), $3, '', TRUE FROM chats WHERE id = $1
+                RETURNING id
+            )
+            UPDATE chat_agents SET bot_user_id = (SELECT id FROM bot)
+            WHERE id = $4
+            RETURNING *
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(&input.name)
+        .bind(agent_bot_email(agent_id))
+        .bind(agent_id)
+        .fetch_one(&mut *tx)
         .await?;
+        tx.commit().await?;
 
         Ok(agent)
     }
@@ -143,62 +176,175 @@ impl AppState {
         Ok(agents)
     }
 
+    /// Get an agent by id
+    pub async fn get_agent_by_id(&self, id: u64) -> Result<Option<ChatAgent>, AppError> {
+        let agent = sqlx::query_as(
+            r#"
+            SELECT * FROM chat_agents WHERE id = $1
+            "#,
+        )
+        .bind(id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(agent)
+    }
+
+    /// Get an agent in a chat
+    pub async fn get_agent(
+        &self,
+        chat_id: u64,
+        agent_id: u64,
+    ) -> Result<Option<ChatAgent>, AppError> {
+        let agent = sqlx::query_as(
+            r#"
+            SELECT * FROM chat_agents WHERE chat_id = $1 AND id = $2
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(agent_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(agent)
+    }
+
     /// update an agent in a chat
     pub async fn update_agent(
         &self,
-        input: UpdateAgent,
         chat_id: u64,
+        agent_id: u64,
+        input: UpdateAgent,
     ) -> Result<ChatAgent, AppError> {
-        let agent_id = input.id;
-        let prompt = input.prompt;
-        let args = input.args;
-
-        // check if agent exists
-        if !self.agent_id_exists(chat_id, agent_id).await? {
+        let Some(agent) = self.get_agent(chat_id, agent_id).await? else {
             info!("Agent {agent_id} does not exist in chat {chat_id}");
-            return Err(AppError::UpdateAgentError(format!(
-                "Agent {} does not exist",
-                agent_id
-            )));
-        }
+            return Err(AppError::NotFound(format!("agent id {agent_id}")));
+        };
 
-        let agent = match (prompt.as_str(), &args) {
-            ("", _) => {
-                sqlx::query_as(
-                    r#"
-                    UPDATE chat_agents SET args = $1 WHERE chat_id = $2 AND id = $3 RETURNING *
-                    "#,
-                )
-                .bind(args)
-                .bind(chat_id as i64)
-                .bind(agent_id as i64)
-                .fetch_one(&self.pool)
-                .await?
+        if let Some(name) = &input.name {
+            if name.is_empty() {
+                return Err(AppError::UpdateAgentError(
+                    "Agent name cannot be empty".to_string(),
+                ));
             }
-            (_, _) => {
-                sqlx::query_as(
-                    r#"
-                    UPDATE chat_agents SET prompt = $1, args = $2 WHERE chat_id = $3 AND id = $4 RETURNING *
-                    "#,
-                )
-                .bind(prompt)
-                .bind(args)
-                .bind(chat_id as i64)
-                .bind(agent_id as i64)
-                .fetch_one(&self.pool)
-                .await?
+            if *name != agent.name && self.agent_name_exists(chat_id, name).await? {
+                return Err(AppError::UpdateAgentError(format!(
+                    "Agent {name} already exists"
+                )));
             }
-        };
+        }
+
+        // the agent must still be buildable once the changes are applied
+        let adapter = input.adapter.as_ref().unwrap_or(&agent.adapter);
+        let provider = input.provider.as_deref().or(agent.provider.as_deref());
+        let model = input.model.as_deref().unwrap_or(&agent.model);
+        self.validate_provider(adapter, provider, model)
+            .map_err(AppError::UpdateAgentError)?;
+
+        if let Some(prompt) = &input.prompt {
+            if let Err(e) = validate_prompt(prompt) {
+                return Err(AppError::UpdateAgentError(format!(
+                    "Invalid prompt template: {e}"
+                )));
+            }
+        }
+
+        let renamed = input.name.as_ref().is_some_and(|name| *name != agent.name);
+        let mut tx = self.pool.begin().await?;
+        let agent: ChatAgent = sqlx::query_as(
+            r#"
+            UPDATE chat_agents
+            SET name = COALESCE($1, name),
+                type = COALESCE($2, type),
+                adapter = COALESCE($3, adapter),
+                provider = COALESCE($4, provider),
+                model = COALESCE($5, model),
+                prompt = COALESCE($6, prompt),
+                args = COALESCE($7, args),
+                updated_at = now()
+            WHERE chat_id = $8 AND id = $9
+            RETURNING *
+            "#,
+        )
+        .bind(input.name)
+        .bind(input.r#type)
+        .bind(input.adapter)
+        .bind(input.provider)
+        .bind(input.model)
+        .bind(input.prompt)
+        .bind(input.args.map(sqlx::types::Json))
+        .bind(chat_id as i64)
+        .bind(agent_id as i64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // the bot user is named after the agent
+        if renamed {
+            sqlx::query("UPDATE users SET fullname = left($1, 64) WHERE id = $2")
+                .bind(&agent.name)
+                .bind(agent.bot_user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
 
         Ok(agent)
     }
+
+    // make sure an agent with these settings could be built from the configured providers
+    fn validate_provider(
+        &self,
+        adapter: &AdapterType,
+        provider: Option<&str>,
+        model: &str,
+    ) -> Result<(), String> {
+        if *adapter == AdapterType::Test {
+            return Ok(());
+        }
+        let Some((name, config)) = self.config.provider(adapter, provider) else {
+            return Err(match provider {
+                Some(name) => format!("Provider {name} is not configured"),
+                None => format!("No provider is configured for adapter {adapter:?}"),
+            });
+        };
+        if config.adapter != *adapter {
+            return Err(format!(
+                "Provider {name} uses {:?} adapter, not {adapter:?}",
+                config.adapter
+            ));
+        }
+        if model.is_empty() && config.default_model.is_none() {
+            return Err(format!(
+                "Model is required as provider {name} has no default model"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Delete an agent from a chat, `chats.agents` is updated by a trigger
+    pub async fn delete_agent(&self, chat_id: u64, agent_id: u64) -> Result<(), AppError> {
+        let ret = sqlx::query(
+            r#"
+            DELETE FROM chat_agents WHERE chat_id = $1 AND id = $2
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(agent_id as i64)
+        .execute(&self.pool)
+        .await?;
+
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("agent id {agent_id}")));
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use anyhow::Result;
-    use std::collections::HashMap;
 
     #[tokio::test]
     async fn create_agent_should_work() -> Result<()> {
@@ -209,7 +355,7 @@ mod tests {
             AdapterType::Ollama,
             "llama3.2",
             "You are a helpful assistant",
-            HashMap::<String, String>::new(),
+            AgentArgs::default(),
         );
         let agent = state
             .create_agent(input, 1)
@@ -221,7 +367,51 @@ mod tests {
         assert_eq!(agent.adapter, AdapterType::Ollama);
         assert_eq!(agent.model, "llama3.2");
         assert_eq!(agent.prompt, "You are a helpful assistant");
-        assert_eq!(agent.args, sqlx::types::Json(serde_json::json!({})));
+        assert_eq!(agent.args, sqlx::types::Json(AgentArgs::default()));
+
+        // the bot user of the agent is created with it
+        let bot_user_id = agent.bot_user_id.expect("bot user should be created");
+        let (fullname, email, is_bot): (String, String, bool) =
+            sqlx::query_as("SELECT fullname, email, is_bot FROM users WHERE id = $1")
+                .bind(bot_user_id)
+                .fetch_one(&state.pool)
+                .await?;
+        assert_eq!(fullname, "test");
+        assert_eq!(email, format!("agent-{}@agents.invalid", agent.id));
+        assert!(is_bot);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_agent_with_invalid_prompt_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateAgent::new(
+            "test",
+            AgentType::Proxy,
+            AdapterType::Ollama,
+            "llama3.2",
+            "You are a {{ args.role ",
+            AgentArgs::default(),
+        );
+        let err = state.create_agent(input, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::CreateAgentError(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_agent_with_unknown_provider_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let mut input = CreateAgent::new(
+            "test",
+            AgentType::Reply,
+            AdapterType::Openai,
+            "gpt-4o",
+            "You are a helpful assistant",
+            AgentArgs::default(),
+        );
+        input.provider = Some("unknown".to_string());
+        let err = state.create_agent(input, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::CreateAgentError(_)));
         Ok(())
     }
 
@@ -233,7 +423,7 @@ mod tests {
         assert_eq!(agents[0].name, "translation");
         assert_eq!(agents[0].r#type, AgentType::Proxy);
         assert_eq!(agents[0].prompt, "If language is Chinese, translate to English, if language is English, translate to Chinese. Please reply with the translated content directly. No explanation is needed. Here is the content: ");
-        assert_eq!(agents[0].args, sqlx::types::Json(serde_json::json!({})));
+        assert_eq!(agents[0].args, sqlx::types::Json(AgentArgs::default()));
         Ok(())
     }
 
@@ -247,24 +437,94 @@ mod tests {
             AdapterType::Ollama,
             "llama3.2",
             "You are a helpful assistant",
-            HashMap::<String, String>::new(),
+            AgentArgs::default(),
         );
         let agent = state
             .create_agent(input, 1)
             .await
             .expect("create agent failed");
         // update the agent
-        let input = UpdateAgent::new(
-            agent.id as u64,
-            "Can you tell me the weather in Tokyo?",
-            HashMap::<String, String>::new(),
-        );
+        let input = UpdateAgent {
+            name: Some("weather".to_string()),
+            r#type: Some(AgentType::Reply),
+            prompt: Some("Can you tell me the weather in Tokyo?".to_string()),
+            ..Default::default()
+        };
         let agent = state
-            .update_agent(input, 1)
+            .update_agent(1, agent.id as _, input)
             .await
             .expect("update agent failed");
+        assert_eq!(agent.name, "weather");
+        assert_eq!(agent.r#type, AgentType::Reply);
+        assert_eq!(agent.adapter, AdapterType::Ollama);
         assert_eq!(agent.prompt, "Can you tell me the weather in Tokyo?");
-        assert_eq!(agent.args, sqlx::types::Json(serde_json::json!({})));
+        assert_eq!(agent.args, sqlx::types::Json(AgentArgs::default()));
+        let bot = state
+            .find_user_by_id(agent.bot_user_id.expect("bot user should exist"))
+            .await?
+            .expect("bot user should exist");
+        assert_eq!(bot.fullname, "weather");
+
+        // name is taken by another agent in the chat
+        let input = UpdateAgent {
+            name: Some("translation".to_string()),
+            ..Default::default()
+        };
+        let err = state
+            .update_agent(1, agent.id as _, input)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::UpdateAgentError(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn agent_name_should_be_unique_per_chat() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateAgent::new(
+            "translation",
+            AgentType::Proxy,
+            AdapterType::Test,
+            "gpt-4o",
+            "You are a helpful assistant",
+            AgentArgs::default(),
+        );
+        state
+            .create_agent(input.clone(), 2)
+            .await
+            .expect("create agent failed");
+        let err = state.create_agent(input, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::CreateAgentError(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_agents_should_be_in_sync() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateAgent::new(
+            "test",
+            AgentType::Tap,
+            AdapterType::Test,
+            "gpt-4o",
+            "You are a helpful assistant",
+            AgentArgs::default(),
+        );
+        let agent = state.create_agent(input, 1).await?;
+        let chat = state.get_chat_by_id(1).await?.expect("chat should exist");
+        assert_eq!(chat.agents, vec![1, agent.id]);
+
+        let found = state.get_agent(1, agent.id as _).await?;
+        assert_eq!(found.map(|a| a.id), Some(agent.id));
+        // agent doesn't belong to chat 2
+        assert!(state.get_agent(2, agent.id as _).await?.is_none());
+
+        state.delete_agent(1, agent.id as _).await?;
+        let chat = state.get_chat_by_id(1).await?.expect("chat should exist");
+        assert_eq!(chat.agents, vec![1]);
+        assert!(state.get_agent(1, agent.id as _).await?.is_none());
+
+        let err = state.delete_agent(1, agent.id as _).await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
         Ok(())
     }
 }
@@ -0,0 +1,117 @@
+use crate::{AppError, AppState};
+use chat_core::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Bookmark {
+    #[sqlx(flatten)]
+    pub message: Message,
+    pub bookmarked_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Bookmark a message in any chat of the user, bookmarking it twice is a no-op.
+    pub async fn add_bookmark(&self, id: u64, user_id: u64) -> Result<(), AppError> {
+        let ret = sqlx::query(
+            r#"
+            INSERT INTO message_bookmarks (user_id, message_id)
+            SELECT $2, m.id
+            FROM messages m
+            JOIN chats c ON c.id = m.chat_id
+            WHERE m.id = $1 AND m.deleted_at IS NULL AND $2 = ANY(c.members)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+
+        // nothing inserted could be an existing bookmark as well
+        if ret.rows_affected() == 0 && !self.has_bookmark(id, user_id).await? {
+            return Err(AppError::NotFound(format!("message id {id}")));
+        }
+        Ok(())
+    }
+
+    pub async fn remove_bookmark(&self, id: u64, user_id: u64) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM message_bookmarks WHERE user_id = $1 AND message_id = $2")
+            .bind(user_id as i64)
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Bookmarks of the user, latest first. Messages in chats the user has left are
+    /// not listed.
+    pub async fn list_bookmarks(&self, user_id: u64) -> Result<Vec<Bookmark>, AppError> {
+        let bookmarks = sqlx::query_as(
+            r#"
+            SELECT m.id, m.chat_id, m.sender_id, m.content, m.modified_content, m.files,
+                m.created_at, m.edited_at, m.reply_to, m.thread_root_id, m.reply_count, m.mentions,
+                m.agent_mentions, b.created_at AS bookmarked_at
+            FROM message_bookmarks b
+            JOIN messages m ON m.id = b.message_id
+            JOIN chats c ON c.id = m.chat_id
+            WHERE b.user_id = $1 AND m.deleted_at IS NULL AND $1 = ANY(c.members)
+            ORDER BY b.created_at DESC, b.message_id DESC
+            "#,
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(bookmarks)
+    }
+
+    async fn has_bookmark(&self, id: u64, user_id: u64) -> Result<bool, AppError> {
+        let ret =
+            sqlx::query("SELECT 1 FROM message_bookmarks WHERE user_id = $1 AND message_id = $2")
+                .bind(user_id as i64)
+                .bind(id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(ret.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CreateMessage;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn bookmarks_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.add_bookmark(2, 4).await?;
+        state.add_bookmark(5, 4).await?;
+        state.add_bookmark(5, 4).await?;
+        let bookmarks = state.list_bookmarks(4).await?;
+        let ids: Vec<_> = bookmarks.iter().map(|b| b.message.id).collect();
+        assert_eq!(ids, [5, 2]);
+
+        // bookmarks are private
+        assert!(state.list_bookmarks(1).await?.is_empty());
+
+        state.remove_bookmark(5, 4).await?;
+        assert_eq!(state.list_bookmarks(4).await?.len(), 1);
+
+        // user 4 isn't in chat 2
+        let input = CreateMessage {
+            content: "private".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        let message = state.create_message(input, 2, 1).await?;
+        let err = state.add_bookmark(message.id as _, 4).await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+}
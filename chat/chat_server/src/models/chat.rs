@@ -1,5 +1,5 @@
 use crate::{AppError, AppState};
-use chat_core::{Chat, ChatType};
+use chat_core::{Chat, ChatRead, ChatType};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -10,6 +10,23 @@ pub struct CreateChat {
     pub public: bool,
 }
 
+#[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize)]
+pub struct UpdateChat {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub members: Option<Vec<i64>>,
+    #[serde(default)]
+    pub public: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize)]
+pub struct MarkRead {
+    /// the last message read, default to the latest message in the chat
+    #[serde(default)]
+    pub message_id: Option<u64>,
+}
+
 #[allow(dead_code)]
 impl AppState {
     pub async fn create_chat(
@@ -68,8 +85,8 @@ impl AppState {
 
         let chat = sqlx::query_as(
             r#"
-            INSERT INTO chats (ws_id, name, type, members)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO chats (ws_id, name, type, members, created_by)
+            VALUES ($1, $2, $3, $4, $5)
             RETURNING id, ws_id, name, type, members, agents, created_at
             "#,
         )
@@ -77,18 +94,185 @@ impl AppState {
         .bind(input.name)
         .bind(chat_type)
         .bind(input.members)
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(chat)
+    }
+
+    /// Update name, members or visibility of a chat. A single chat can't be changed. Any
+    /// member could rename it, only the creator or workspace owner could change members or
+    /// visibility (only the workspace owner if the chat has no creator).
+    pub async fn update_chat(
+        &self,
+        id: u64,
+        input: UpdateChat,
+        user_id: u64,
+    ) -> Result<Chat, AppError> {
+        let Some(chat) = self.get_chat_by_id(id).await? else {
+            return Err(AppError::NotFound(format!("chat id {id}")));
+        };
+
+        if chat.r#type == ChatType::Single {
+            let members_changed = match &input.members {
+                Some(members) => {
+                    members.len() != chat.members.len()
+                        || members.iter().any(|m| !chat.members.contains(m))
+                }
+                None => false,
+            };
+            if members_changed || input.name.is_some() || input.public.is_some() {
+                return Err(AppError::UpdateChatError(
+                    "Single chat cannot be changed".to_string(),
+                ));
+            }
+            return Ok(chat);
+        }
+
+        let name = input.name.or(chat.name);
+        let members = input.members.unwrap_or(chat.members);
+        let len = members.len();
+        if len < 2 {
+            return Err(AppError::UpdateChatError(
+                "Chat must have at least 2 members".to_string(),
+            ));
+        }
+
+        if !members.contains(&(user_id as i64)) {
+            return Err(AppError::UpdateChatError(
+                "You must be a member of the chat".to_string(),
+            ));
+        }
+
+        if let Some(name) = &name {
+            if name.len() < 3 {
+                return Err(AppError::UpdateChatError(
+                    "Chat name must have at least 3 characters".to_string(),
+                ));
+            }
+        }
+
+        if len > 8 && name.is_none() {
+            return Err(AppError::UpdateChatError(
+                "Group chat with more than 8 members must have a name".to_string(),
+            ));
+        }
+
+        let users = self.fetch_chat_user_by_ids(&members).await?;
+        if users.len() != len {
+            return Err(AppError::UpdateChatError(
+                "Some members do not exist".to_string(),
+            ));
+        }
+
+        if name.is_none() && input.public == Some(true) {
+            return Err(AppError::UpdateChatError(
+                "Group chat without a name cannot be public".to_string(),
+            ));
+        }
+
+        // an unnamed group stays a group even if it shrinks to 2 members
+        let was_public = chat.r#type == ChatType::PublicChannel;
+        let public = input.public.unwrap_or(was_public);
+        let chat_type = match (&name, public) {
+            (None, _) => ChatType::Group,
+            (Some(_), true) => ChatType::PublicChannel,
+            (Some(_), false) => ChatType::PrivateChannel,
+        };
+
+        let members_changed = members.len() != chat.members.len()
+            || members.iter().any(|m| !chat.members.contains(m));
+        if members_changed || public != was_public {
+            self.verify_chat_admin(id, user_id, "change members or visibility of")
+                .await?;
+        }
+
+        let chat = sqlx::query_as(
+            r#"
+            UPDATE chats
+            SET name = $1, type = $2, members = $3
+            WHERE id = $4
+            RETURNING id, ws_id, name, type, members, agents, created_at
+            "#,
+        )
+        .bind(name)
+        .bind(chat_type)
+        .bind(members)
+        .bind(id as i64)
         .fetch_one(&self.pool)
         .await?;
 
         Ok(chat)
     }
 
+    /// Delete a chat with its messages and agents. Only the creator or workspace owner could do
+    /// it, a chat without a creator only by the workspace owner.
+    pub async fn delete_chat(&self, id: u64, user_id: u64) -> Result<(), AppError> {
+        self.verify_chat_admin(id, user_id, "delete").await?;
+
+        sqlx::query("DELETE FROM chats WHERE id = $1")
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // the creator of a chat and the owner of its workspace could manage it. Chats without a
+    // creator (created before it was tracked and not backfilled) are managed by the owner only.
+    async fn verify_chat_admin(&self, id: u64, user_id: u64, action: &str) -> Result<(), AppError> {
+        let row: Option<(Option<i64>, i64)> = sqlx::query_as(
+            r#"
+            SELECT c.created_by, w.owner_id
+            FROM chats c
+            JOIN workspaces w ON w.id = c.ws_id
+            WHERE c.id = $1
+            "#,
+        )
+        .bind(id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((created_by, owner_id)) = row else {
+            return Err(AppError::NotFound(format!("chat id {id}")));
+        };
+
+        let user_id = user_id as i64;
+        if created_by != Some(user_id) && owner_id != user_id {
+            return Err(AppError::PermissionDenied(format!(
+                "only chat creator or workspace owner could {action} chat {id}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// List chats of the user with their pins, read position and unread count. Messages
+    /// sent by the user and thread replies are not counted.
     pub async fn fetch_chats(&self, user_id: u64, ws_id: u64) -> Result<Vec<Chat>, AppError> {
         let chats = sqlx::query_as(
             r#"
-            SELECT id, ws_id, name, type, members, agents, created_at
-            FROM chats
-            WHERE ws_id = $1 AND $2 = ANY(members)
+            SELECT c.id, c.ws_id, c.name, c.type, c.members, c.agents, c.created_at,
+                r.last_read_message_id,
+                (
+                    SELECT count(*)
+                    FROM messages m
+                    WHERE m.chat_id = c.id
+                    AND m.id > COALESCE(r.last_read_message_id, 0)
+                    AND m.sender_id != $2
+                    AND m.deleted_at IS NULL
+                    AND m.thread_root_id IS NULL
+                ) AS unread_count,
+                ARRAY(
+                    SELECT p.message_id
+                    FROM message_pins p
+                    JOIN messages m ON m.id = p.message_id
+                    WHERE p.chat_id = c.id AND m.deleted_at IS NULL
+                    ORDER BY p.created_at DESC, p.message_id DESC
+                ) AS pins
+            FROM chats c
+            LEFT JOIN chat_reads r ON r.chat_id = c.id AND r.user_id = $2
+            WHERE c.ws_id = $1 AND $2 = ANY(c.members)
             "#,
         )
         .bind(ws_id as i64)
@@ -102,9 +286,16 @@ impl AppState {
     pub async fn get_chat_by_id(&self, id: u64) -> Result<Option<Chat>, AppError> {
         let chat = sqlx::query_as(
             r#"
-            SELECT id, ws_id, name, type, members, agents, created_at
-            FROM chats
-            WHERE id = $1
+            SELECT c.id, c.ws_id, c.name, c.type, c.members, c.agents, c.created_at,
+                ARRAY(
+                    SELECT p.message_id
+                    FROM message_pins p
+                    JOIN messages m ON m.id = p.message_id
+                    WHERE p.chat_id = c.id AND m.deleted_at IS NULL
+                    ORDER BY p.created_at DESC, p.message_id DESC
+                ) AS pins
+            FROM chats c
+            WHERE c.id = $1
             "#,
         )
         .bind(id as i64)
@@ -114,6 +305,19 @@ impl AppState {
         Ok(chat)
     }
 
+    /// Move the read position of the user forward, it never goes back.
+    pub async fn mark_chat_read(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        input: MarkRead,
+    ) -> Result<ChatRead, AppError> {
+        let message_id = input.message_id.map(|id| id as i64);
+        let read =
+            chat_core::mark_chat_read(&self.pool, chat_id as _, user_id as _, message_id).await?;
+        read.ok_or_else(|| AppError::NotFound(format!("message id {}", message_id.unwrap_or(0))))
+    }
+
     pub async fn is_chat_member(&self, chat_id: u64, user_id: u64) -> Result<bool, AppError> {
         let is_member = sqlx::query(
             r#"
@@ -147,9 +351,21 @@ impl CreateChat {
     }
 }
 
+#[cfg(test)]
+impl UpdateChat {
+    pub fn new(name: Option<&str>, members: Option<&[i64]>, public: Option<bool>) -> Self {
+        Self {
+            name: name.map(|v| v.to_string()),
+            members: members.map(|v| v.to_vec()),
+            public,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::CreateMessage;
     use anyhow::Result;
 
     #[tokio::test]
@@ -180,6 +396,102 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn update_chat_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat::new("private", &[1, 2, 3], false);
+        let chat = state.create_chat(input, 1, 1).await?;
+        let id = chat.id as u64;
+
+        // add member 4 to private channel and make it public
+        let input = UpdateChat::new(Some("private1"), Some(&[1, 2, 3, 4]), Some(true));
+        let chat = state
+            .update_chat(id, input, 1)
+            .await
+            .expect("update chat failed");
+        assert_eq!(chat.name.as_deref(), Some("private1"));
+        assert_eq!(chat.members, vec![1, 2, 3, 4]);
+        assert_eq!(chat.r#type, ChatType::PublicChannel);
+
+        // other members could rename it, but not change members or visibility
+        let input = UpdateChat::new(Some("private2"), None, None);
+        let chat = state.update_chat(id, input, 2).await?;
+        assert_eq!(chat.name.as_deref(), Some("private2"));
+        for input in [
+            UpdateChat::new(None, Some(&[1, 2, 3]), None),
+            UpdateChat::new(None, None, Some(false)),
+        ] {
+            let err = state.update_chat(id, input, 2).await.unwrap_err();
+            assert!(matches!(err, AppError::PermissionDenied(_)));
+        }
+
+        // naming a group turns it into a channel
+        let input = UpdateChat::new(Some("group1"), None, None);
+        let chat = state
+            .update_chat(4, input, 1)
+            .await
+            .expect("update chat failed");
+        assert_eq!(chat.r#type, ChatType::PrivateChannel);
+
+        // an unnamed group can't be public
+        let input = UpdateChat::new(None, None, Some(true));
+        let chat = state
+            .create_chat(
+                CreateChat {
+                    name: None,
+                    members: vec![1, 2, 3],
+                    public: false,
+                },
+                1,
+                1,
+            )
+            .await?;
+        let err = state.update_chat(chat.id as _, input, 1).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "update chat error: Group chat without a name cannot be public"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_single_chat_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = UpdateChat::new(None, Some(&[1, 2, 3]), None);
+        let err = state.update_chat(3, input, 1).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "update chat error: Single chat cannot be changed"
+        );
+
+        let input = UpdateChat::new(None, Some(&[1]), None);
+        let err = state.update_chat(2, input, 1).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "update chat error: Chat must have at least 2 members"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_chat_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat::new("general1", &[1, 2, 3], true);
+        let chat = state.create_chat(input, 1, 1).await?;
+
+        // only the creator or workspace owner could delete the chat
+        let err = state.delete_chat(chat.id as _, 2).await.unwrap_err();
+        assert!(matches!(err, AppError::PermissionDenied(_)));
+
+        state.delete_chat(chat.id as _, 1).await?;
+        assert!(state.get_chat_by_id(chat.id as _).await?.is_none());
+
+        // chat 1 has messages and agents, workspace owner is user 0
+        state.delete_chat(1, 0).await?;
+        assert!(state.get_chat_by_id(1).await?.is_none());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn chat_get_by_id_should_work() -> Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
@@ -210,6 +522,91 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn mark_chat_read_should_update_unread_count() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let unread = |chats: Vec<Chat>| chats.into_iter().find(|c| c.id == 1).unwrap();
+
+        // messages sent by user 1 are not unread
+        let chat = unread(state.fetch_chats(1, 1).await?);
+        assert_eq!(chat.unread_count, 6);
+        assert_eq!(chat.last_read_message_id, None);
+
+        let read = state
+            .mark_chat_read(
+                1,
+                1,
+                MarkRead {
+                    message_id: Some(5),
+                },
+            )
+            .await?;
+        assert_eq!(read.last_read_message_id, 5);
+        let chat = unread(state.fetch_chats(1, 1).await?);
+        assert_eq!(chat.unread_count, 2);
+        assert_eq!(chat.last_read_message_id, Some(5));
+
+        // read position never goes back
+        let read = state
+            .mark_chat_read(
+                1,
+                1,
+                MarkRead {
+                    message_id: Some(3),
+                },
+            )
+            .await?;
+        assert_eq!(read.last_read_message_id, 5);
+
+        // default to the latest message
+        let read = state.mark_chat_read(1, 1, MarkRead::default()).await?;
+        assert_eq!(read.last_read_message_id, 10);
+        let chat = unread(state.fetch_chats(1, 1).await?);
+        assert_eq!(chat.unread_count, 0);
+
+        // other users are not affected
+        let chat = unread(state.fetch_chats(2, 1).await?);
+        assert_eq!(chat.unread_count, 8);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mark_chat_read_with_invalid_message_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let err = state
+            .mark_chat_read(
+                2,
+                1,
+                MarkRead {
+                    message_id: Some(1),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+
+        // thread replies are not part of the read position
+        let input = CreateMessage {
+            content: "reply".to_string(),
+            files: vec![],
+            reply_to: Some(1),
+            send_at: None,
+        };
+        let reply = state.create_message(input, 1, 2).await?;
+        let err = state
+            .mark_chat_read(
+                1,
+                1,
+                MarkRead {
+                    message_id: Some(reply.id as _),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn chat_is_member_should_work() -> Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
@@ -0,0 +1,221 @@
+use crate::{AppError, AppState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "agent_job_status", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum AgentJobStatus {
+    Pending,
+    Running,
+    Done,
+    Dead,
+}
+
+/// A queued agent run for a message, stored in `agent_jobs`.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentJob {
+    pub id: i64,
+    pub agent_id: i64,
+    pub message_id: i64,
+    pub status: AgentJobStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[allow(dead_code)]
+impl AppState {
+    /// Queue an agent run for a message
+    pub async fn enqueue_agent_job(
+        &self,
+        agent_id: u64,
+        message_id: u64,
+    ) -> Result<AgentJob, AppError> {
+        let job = sqlx::query_as(
+            r#"
+            INSERT INTO agent_jobs (agent_id, message_id)
+            VALUES ($1, $2)
+            RETURNING *
+            "#,
+        )
+        .bind(agent_id as i64)
+        .bind(message_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(job)
+    }
+
+    /// Claim the next job that is due. Jobs stuck in running state for longer than the
+    /// worker timeout (e.g. the worker crashed) are claimed again, or marked as dead if
+    /// they have run out of attempts.
+    pub async fn claim_agent_job(&self) -> Result<Option<AgentJob>, AppError> {
+        let config = &self.config.worker;
+        let job = sqlx::query_as(
+            r#"
+            WITH dead AS (
+                UPDATE agent_jobs
+                SET status = 'dead', last_error = 'timed out', updated_at = now()
+                WHERE status = 'running' AND attempts >= $2
+                AND updated_at < now() - make_interval(secs => $1)
+            )
+            UPDATE agent_jobs
+            SET status = 'running', attempts = attempts + 1, updated_at = now()
+            WHERE id = (
+                SELECT id FROM agent_jobs
+                WHERE (status = 'pending' AND run_at <= now())
+                OR (status = 'running' AND attempts < $2
+                    AND updated_at < now() - make_interval(secs => $1))
+                ORDER BY run_at, id
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(config.timeout as f64)
+        .bind(config.max_attempts)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(job)
+    }
+
+    /// Mark a job as done
+    pub async fn complete_agent_job(&self, id: u64) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE agent_jobs SET status = 'done', last_error = NULL, updated_at = now()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Reschedule a failed job with exponential backoff, or mark it as dead if it
+    /// has run out of attempts.
+    pub async fn fail_agent_job(&self, job: &AgentJob, error: &str) -> Result<AgentJob, AppError> {
+        let config = &self.config.worker;
+        let job = if job.attempts >= config.max_attempts {
+            sqlx::query_as(
+                r#"
+                UPDATE agent_jobs SET status = 'dead', last_error = $1, updated_at = now()
+                WHERE id = $2
+                RETURNING *
+                "#,
+            )
+            .bind(error)
+            .bind(job.id)
+            .fetch_one(&self.pool)
+            .await?
+        } else {
+            let delay = config.backoff_delay(job.attempts).as_secs_f64();
+            sqlx::query_as(
+                r#"
+                UPDATE agent_jobs
+                SET status = 'pending', last_error = $1, run_at = now() + make_interval(secs => $2), updated_at = now()
+                WHERE id = $3
+                RETURNING *
+                "#,
+            )
+            .bind(error)
+            .bind(delay)
+            .bind(job.id)
+            .fetch_one(&self.pool)
+            .await?
+        };
+
+        Ok(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn agent_job_should_be_claimed_once() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let job = state.enqueue_agent_job(1, 1).await?;
+        assert_eq!(job.status, AgentJobStatus::Pending);
+
+        let claimed = state.claim_agent_job().await?.expect("job should exist");
+        assert_eq!(claimed.id, job.id);
+        assert_eq!(claimed.status, AgentJobStatus::Running);
+        assert_eq!(claimed.attempts, 1);
+
+        // running job can't be claimed again
+        assert!(state.claim_agent_job().await?.is_none());
+
+        state.complete_agent_job(claimed.id as _).await?;
+        assert!(state.claim_agent_job().await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_agent_job_should_retry_then_die() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.enqueue_agent_job(1, 1).await?;
+        let max_attempts = state.config.worker.max_attempts;
+
+        let mut job = state.claim_agent_job().await?.expect("job should exist");
+        for _ in 1..max_attempts {
+            let failed = state.fail_agent_job(&job, "network error").await?;
+            assert_eq!(failed.status, AgentJobStatus::Pending);
+            assert!(failed.run_at > job.run_at);
+            // not due yet because of backoff
+            assert!(state.claim_agent_job().await?.is_none());
+
+            sqlx::query("UPDATE agent_jobs SET run_at = now() WHERE id = $1")
+                .bind(job.id)
+                .execute(&state.pool)
+                .await?;
+            job = state.claim_agent_job().await?.expect("job should exist");
+        }
+
+        assert_eq!(job.attempts, max_attempts);
+        let job = state.fail_agent_job(&job, "network error").await?;
+        assert_eq!(job.status, AgentJobStatus::Dead);
+        assert_eq!(job.last_error.as_deref(), Some("network error"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stuck_agent_job_should_die_after_max_attempts() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let job = state.enqueue_agent_job(1, 1).await?;
+        let max_attempts = state.config.worker.max_attempts;
+
+        // the worker hangs or crashes on every attempt
+        for attempt in 1..=max_attempts {
+            let claimed = state.claim_agent_job().await?.expect("job should exist");
+            assert_eq!(claimed.attempts, attempt);
+            sqlx::query(
+                "UPDATE agent_jobs SET updated_at = now() - interval '1 day' WHERE id = $1",
+            )
+            .bind(job.id)
+            .execute(&state.pool)
+            .await?;
+        }
+
+        assert!(state.claim_agent_job().await?.is_none());
+        let (status, last_error): (AgentJobStatus, Option<String>) =
+            sqlx::query_as("SELECT status, last_error FROM agent_jobs WHERE id = $1")
+                .bind(job.id)
+                .fetch_one(&state.pool)
+                .await?;
+        assert_eq!(status, AgentJobStatus::Dead);
+        assert_eq!(last_error.as_deref(), Some("timed out"));
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use chat_core::{ChatAgent, ChatUser};
+
+/// Users and agents mentioned in a message
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Mentions {
+    pub users: Vec<i64>,
+    pub agents: Vec<i64>,
+}
+
+impl Mentions {
+    /// `@handle` mentions a member by the local part of their email (e.g. `@alice` for
+    /// alice@acme.org), or an agent of the chat by its name. Members take precedence over
+    /// agents, unknown handles and the sender themselves are ignored.
+    pub(crate) fn parse(
+        content: &str,
+        sender_id: i64,
+        members: &[ChatUser],
+        agents: &[ChatAgent],
+    ) -> Self {
+        let mut ret = Self::default();
+        for handle in parse_mention_handles(content) {
+            let user = members
+                .iter()
+                .find(|u| email_handle(&u.email).eq_ignore_ascii_case(handle));
+            if let Some(user) = user {
+                if user.id != sender_id && !ret.users.contains(&user.id) {
+                    ret.users.push(user.id);
+                }
+                continue;
+            }
+            let agent = agents.iter().find(|a| a.name.eq_ignore_ascii_case(handle));
+            if let Some(agent) = agent {
+                if !ret.agents.contains(&agent.id) {
+                    ret.agents.push(agent.id);
+                }
+            }
+        }
+        ret
+    }
+}
+
+// `@handle` tokens in the content. An `@` right after a word character (e.g. in an email)
+// doesn't start a mention, trailing punctuation isn't part of the handle.
+fn parse_mention_handles(content: &str) -> Vec<&str> {
+    let mut handles = Vec::new();
+    let mut prev = None;
+    for (i, c) in content.char_indices() {
+        if c == '@' && !prev.is_some_and(is_handle_char) {
+            let rest = &content[i + 1..];
+            let end = rest
+                .find(|c: char| !is_handle_char(c))
+                .unwrap_or(rest.len());
+            let handle = rest[..end].trim_end_matches(['.', '-']);
+            if !handle.is_empty() {
+                handles.push(handle);
+            }
+        }
+        prev = Some(c);
+    }
+    handles
+}
+
+fn is_handle_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+fn email_handle(email: &str) -> &str {
+    email.split('@').next().unwrap_or(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, CreateAgent, CreateMessage, EditMessage};
+    use anyhow::Result;
+    use chat_core::{AdapterType, AgentArgs, AgentType};
+
+    #[test]
+    fn parse_mention_handles_should_work() {
+        let handles = parse_mention_handles("@alice, ask @bob.chen. mail tchen@acme.org @ @-");
+        assert_eq!(handles, ["alice", "bob.chen"]);
+    }
+
+    #[tokio::test]
+    async fn create_message_should_store_mentions() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // user 1 mentioning themselves and someone not in the chat is ignored
+        let input = CreateMessage {
+            content: "@Alice @bob @tchen @nobody @alice".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        let message = state.create_message(input, 2, 1).await?;
+        assert_eq!(message.mentions, [2, 3]);
+        assert!(message.agent_mentions.is_empty());
+
+        let input = EditMessage {
+            content: "never mind @bob".to_string(),
+        };
+        let message = state.edit_message(2, message.id as _, input, 1).await?;
+        assert_eq!(message.mentions, [3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn agent_on_mention_should_only_run_when_mentioned() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let args = AgentArgs {
+            on_mention: true,
+            ..Default::default()
+        };
+        let input = CreateAgent::new(
+            "archive",
+            AgentType::Tap,
+            AdapterType::Test,
+            "gpt-4o",
+            "You are a helpful assistant",
+            args,
+        );
+        let agent = state.create_agent(input, 1).await?;
+
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        state.create_message(input, 1, 1).await?;
+        assert!(state.claim_agent_job().await?.is_none());
+
+        let input = CreateMessage {
+            content: "@archive this please".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        let message = state.create_message(input, 1, 1).await?;
+        assert_eq!(message.agent_mentions, [agent.id]);
+        let job = state.claim_agent_job().await?.expect("job should exist");
+        assert_eq!(job.message_id, message.id);
+        Ok(())
+    }
+}
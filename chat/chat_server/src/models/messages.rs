@@ -1,25 +1,89 @@
-use crate::{agent::AgentVariant, AppError, AppState, ChatFile};
-use chat_core::{Agent, AgentContext, AgentDecision, ChatType, Message};
+use crate::{
+    agent::{AgentVariant, ReplyAgent},
+    models::{scheduled::mark_scheduled_message_sent, Mentions},
+    AppError, AppState, ChatFile,
+};
+use ai_sdk::CompletionStream;
+use chat_core::{Agent, AgentContext, AgentDecision, AgentType, ChatAgent, ChatType, Message};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use tracing::warn;
 use utoipa::{IntoParams, ToSchema};
 
+// how often a streaming agent reply is flushed to db (and thus pushed to clients)
+const REPLY_FLUSH_INTERVAL: Duration = Duration::from_millis(300);
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
 pub struct CreateMessage {
     pub content: String,
     #[serde(default)]
     pub files: Vec<String>,
+    /// reply to a message in its thread
+    #[serde(default)]
+    pub reply_to: Option<u64>,
+    /// send the message later instead, it's delivered by the scheduler
+    #[serde(default)]
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct EditMessage {
+    pub content: String,
+}
+
+/// A previous version of an edited message, `created_at` is when it was replaced.
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct MessageEdit {
+    pub id: i64,
+    pub message_id: i64,
+    pub content: String,
+    pub modified_content: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, IntoParams, ToSchema, Serialize, Deserialize)]
+/// At most one of `before`, `after` and `around` could be given, without any the latest
+/// messages are returned.
+#[derive(Debug, Clone, Default, IntoParams, ToSchema, Serialize, Deserialize)]
 pub struct ListMessages {
+    /// messages older than this id
+    #[serde(default, alias = "last_id")]
+    pub before: Option<u64>,
+    /// messages newer than this id
+    #[serde(default)]
+    pub after: Option<u64>,
+    /// messages around this id, the message itself included. A thread reply is found by its root
     #[serde(default)]
-    pub last_id: Option<u64>,
+    pub around: Option<u64>,
+    /// defaults to 50, at most 100
     #[serde(default)]
     pub limit: u64,
 }
 
+/// A page of messages, latest first. Pass `prev` as `before` to load older messages and
+/// `next` as `after` to load newer ones, they are absent when there's nothing more.
+#[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize, PartialEq)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    pub prev: Option<i64>,
+    pub next: Option<i64>,
+}
+
+// what an agent decided for a message, stored in `agent_decisions`
+pub(crate) struct AgentDecisionRecord {
+    agent_id: i64,
+    decision: AgentDecision,
+    reply_id: Option<i64>,
+}
+
 #[allow(dead_code)]
 impl AppState {
     pub async fn create_message(
@@ -28,6 +92,136 @@ impl AppState {
         chat_id: u64,
         user_id: u64,
     ) -> Result<Message, AppError> {
+        self.send_message(input, chat_id, user_id, None).await
+    }
+
+    // a scheduled message being delivered is marked as sent along with the message insert
+    pub(crate) async fn send_message(
+        &self,
+        input: CreateMessage,
+        chat_id: u64,
+        user_id: u64,
+        scheduled_id: Option<i64>,
+    ) -> Result<Message, AppError> {
+        self.verify_message_input(&input)?;
+
+        // replies hang on the root of the thread
+        let thread_root_id = match input.reply_to {
+            Some(id) => Some(self.get_thread_root_id(chat_id, id).await?),
+            None => None,
+        };
+
+        let ctx = self
+            .build_agent_context(chat_id, user_id, None, thread_root_id)
+            .await?;
+        let agents = self.list_agents(chat_id).await?;
+        let mentions = Mentions::parse(&input.content, user_id as _, &ctx.members, &agents);
+
+        // agents run in a deterministic order: proxies first (each one sees the content
+        // modified by the previous one), then reply agents, then tap agents. Agents only
+        // running on mention are skipped unless mentioned.
+        let (proxies, agents): (Vec<_>, Vec<_>) = agents
+            .into_iter()
+            .filter(|a| !a.args.on_mention || mentions.agents.contains(&a.id))
+            .partition(|a| a.r#type == AgentType::Proxy);
+        let (replies, taps): (Vec<_>, Vec<_>) = agents
+            .into_iter()
+            .partition(|a| a.r#type == AgentType::Reply);
+        let (modified_content, mut records) =
+            self.run_proxy_agents(proxies, &input.content, &ctx).await?;
+        if let Some(agent_id) = deleted_by(&records) {
+            self.record_agent_decisions(chat_id, None, &records).await?;
+            return Err(AppError::CreateMessageError(format!(
+                "Message is deleted by agent {agent_id}"
+            )));
+        }
+        let content = modified_content
+            .clone()
+            .unwrap_or_else(|| input.content.clone());
+
+        // create message, with the reply count of its thread
+        let mut tx = self.pool.begin().await?;
+        let message: Message = sqlx::query_as(
+            r#"
+          INSERT INTO messages (chat_id, sender_id, content, modified_content, files, reply_to, thread_root_id,
+            mentions, agent_mentions)
+          VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+          RETURNING *
+          "#,
+        )
+        .bind(chat_id as i64)
+        .bind(user_id as i64)
+        .bind(input.content)
+        .bind(modified_content)
+        .bind(&input.files)
+        .bind(input.reply_to.map(|id| id as i64))
+        .bind(thread_root_id)
+        .bind(&mentions.users)
+        .bind(&mentions.agents)
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(root_id) = thread_root_id {
+            update_reply_count(&mut tx, root_id, 1).await?;
+        }
+        if let Some(id) = scheduled_id {
+            mark_scheduled_message_sent(&mut tx, id, message.id).await?;
+        }
+        tx.commit().await?;
+
+        // a proxy may reply directly as well
+        for record in records.iter_mut() {
+            if let AgentDecision::Reply(reply) = &record.decision {
+                let reply_id = self
+                    .create_agent_reply(&message, record.agent_id, reply)
+                    .await?;
+                record.reply_id = Some(reply_id);
+            }
+        }
+
+        // the message is already stored, so failed reply agents shouldn't fail the request
+        for agent in replies {
+            let agent_id = agent.id;
+            let ret = match AgentVariant::try_new(agent, &self.config) {
+                // reply agents stream their answer into a message which is updated as tokens arrive
+                Ok(AgentVariant::Reply(agent)) => self
+                    .stream_agent_reply(agent_id, &agent, &message, &content, &ctx)
+                    .await
+                    .map(|id| {
+                        AgentDecisionRecord::new(agent_id, AgentDecision::Reply("".into()))
+                            .with_reply(id)
+                    }),
+                Ok(agent) => match agent.process(&content, &ctx).await {
+                    Ok(AgentDecision::Reply(reply)) => {
+                        let ret = self.create_agent_reply(&message, agent_id, &reply).await;
+                        ret.map(|id| {
+                            AgentDecisionRecord::new(agent_id, AgentDecision::Reply(reply))
+                                .with_reply(id)
+                        })
+                    }
+                    Ok(decision) => Ok(AgentDecisionRecord::new(agent_id, decision)),
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e),
+            };
+            match ret {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("agent {} failed on message {}: {}", agent_id, message.id, e),
+            }
+        }
+
+        // tap agents don't change the conversation, they are queued and run by the worker
+        for agent in taps {
+            self.enqueue_agent_job(agent.id as _, message.id as _)
+                .await?;
+        }
+
+        self.record_agent_decisions(chat_id, Some(message.id), &records)
+            .await?;
+
+        Ok(message)
+    }
+
+    pub(crate) fn verify_message_input(&self, input: &CreateMessage) -> Result<(), AppError> {
         let base_dir = &self.config.server.base_dir;
         // verify content - not empty
         if input.content.is_empty() {
@@ -46,114 +240,698 @@ impl AppState {
                 )));
             }
         }
+        Ok(())
+    }
+
+    /// Create an empty reply message right away, then fill it in the background with the
+    /// tokens generated by the agent. Every flush triggers `chat_message_updated`.
+    async fn stream_agent_reply(
+        &self,
+        agent_id: i64,
+        agent: &ReplyAgent,
+        msg: &Message,
+        content: &str,
+        ctx: &AgentContext,
+    ) -> Result<i64, AppError> {
+        let stream = agent.process_stream(content, ctx).await?;
+        let id = self
+            .insert_agent_reply(msg, agent_id, "", agent.args.reply_in_thread)
+            .await?;
+
+        let state = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state.fill_agent_reply(id, stream).await {
+                warn!("failed to stream agent reply into message {}: {}", id, e);
+            }
+        });
+
+        Ok(id)
+    }
+
+    async fn create_agent_reply(
+        &self,
+        msg: &Message,
+        agent_id: i64,
+        reply: &str,
+    ) -> Result<i64, AppError> {
+        self.insert_agent_reply(msg, agent_id, reply, false).await
+    }
+
+    // agent replies stay in the thread of the message, or start one under it if the
+    // agent is configured to reply in thread
+    async fn insert_agent_reply(
+        &self,
+        msg: &Message,
+        agent_id: i64,
+        content: &str,
+        in_thread: bool,
+    ) -> Result<i64, AppError> {
+        let sender_id = self
+            .get_reply_sender(msg.chat_id as _, msg.sender_id as _, agent_id)
+            .await?;
+        let thread_root_id = msg.thread_root_id.or(in_thread.then_some(msg.id));
+        let reply_to = thread_root_id.map(|_| msg.id);
+        let mut tx = self.pool.begin().await?;
+        let (id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO messages (chat_id, sender_id, content, reply_to, thread_root_id)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            "#,
+        )
+        .bind(msg.chat_id)
+        .bind(sender_id)
+        .bind(content)
+        .bind(reply_to)
+        .bind(thread_root_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(root_id) = thread_root_id {
+            update_reply_count(&mut tx, root_id, 1).await?;
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    // the root of the thread a reply to message `id` goes into
+    pub(crate) async fn get_thread_root_id(&self, chat_id: u64, id: u64) -> Result<i64, AppError> {
+        match self.get_message_by_id(id).await? {
+            Some(msg) if msg.chat_id == chat_id as i64 => Ok(msg.thread_root_id.unwrap_or(msg.id)),
+            _ => Err(AppError::CreateMessageError(format!(
+                "Message {id} to reply doesn't exist"
+            ))),
+        }
+    }
+
+    /// List a thread: the root message followed by its replies, oldest first. `id` could
+    /// be the root or any reply in the thread.
+    pub async fn list_thread_messages(
+        &self,
+        chat_id: u64,
+        id: u64,
+    ) -> Result<Vec<Message>, AppError> {
+        let root_id = match self.get_message_by_id(id).await? {
+            Some(msg) if msg.chat_id == chat_id as i64 => msg.thread_root_id.unwrap_or(msg.id),
+            _ => return Err(AppError::NotFound(format!("message id {id}"))),
+        };
+
+        let mut messages: Vec<Message> = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+                reply_to, thread_root_id, reply_count, mentions, agent_mentions
+            FROM messages
+            WHERE chat_id = $1 AND (id = $2 OR thread_root_id = $2) AND deleted_at IS NULL
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(root_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.attach_reactions(&mut messages).await?;
+
+        Ok(messages)
+    }
+
+    pub(crate) async fn record_agent_decisions(
+        &self,
+        chat_id: u64,
+        message_id: Option<i64>,
+        records: &[AgentDecisionRecord],
+    ) -> Result<(), AppError> {
+        for record in records {
+            let (decision, content) = match &record.decision {
+                AgentDecision::Modify(s) => ("modify", Some(s.as_str())),
+                // streamed replies have no content here, it lives in the reply message
+                AgentDecision::Reply(s) => ("reply", (!s.is_empty()).then_some(s.as_str())),
+                AgentDecision::Delete => ("delete", None),
+                AgentDecision::None => ("none", None),
+                AgentDecision::Tap(s) => ("tap", Some(s.as_str())),
+            };
+            sqlx::query(
+                r#"
+                INSERT INTO agent_decisions (chat_id, message_id, agent_id, decision, content, reply_id)
+                VALUES ($1, $2, $3, $4::agent_decision_type, $5, $6)
+                "#,
+            )
+            .bind(chat_id as i64)
+            .bind(message_id)
+            .bind(record.agent_id)
+            .bind(decision)
+            .bind(content)
+            .bind(record.reply_id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn fill_agent_reply(
+        &self,
+        id: i64,
+        mut stream: CompletionStream,
+    ) -> Result<(), AppError> {
+        let mut content = String::new();
+        let mut flushed_at = Instant::now();
+        let mut ret = Ok(());
+        while let Some(delta) = stream.next().await {
+            match delta {
+                Ok(delta) => content.push_str(&delta),
+                Err(e) => {
+                    ret = Err(AppError::AnyError(e));
+                    break;
+                }
+            }
+            if flushed_at.elapsed() >= REPLY_FLUSH_INTERVAL {
+                self.update_message_content(id, &content).await?;
+                flushed_at = Instant::now();
+            }
+        }
+        // always write what we've got so far, even if the stream failed in the middle
+        self.update_message_content(id, &content).await?;
+        ret
+    }
 
-        // if we have agent, apply it and get the result
-        let mut agents = self.list_agents(chat_id).await?;
-        let decision = if let Some(agent) = agents.pop() {
-            let agent: AgentVariant = agent.into();
-            match agent {
-                AgentVariant::Proxy(agent) => {
-                    agent
-                        .process(&input.content, &AgentContext::default())
-                        .await?
+    // proxies run in order, each one sees the content modified by the previous one. They
+    // stop at the first one deleting the message, its decision is the last record then.
+    async fn run_proxy_agents(
+        &self,
+        proxies: Vec<ChatAgent>,
+        content: &str,
+        ctx: &AgentContext,
+    ) -> Result<(Option<String>, Vec<AgentDecisionRecord>), AppError> {
+        let mut modified_content: Option<String> = None;
+        let mut records = Vec::new();
+        for agent in proxies {
+            let agent_id = agent.id;
+            let decision = AgentVariant::try_new(agent, &self.config)?
+                .process(modified_content.as_deref().unwrap_or(content), ctx)
+                .await?;
+            match &decision {
+                AgentDecision::Modify(s) => modified_content = Some(s.clone()),
+                AgentDecision::Delete => {
+                    records.push(AgentDecisionRecord::new(agent_id, decision));
+                    break;
                 }
-                _ => AgentDecision::None,
+                _ => {}
             }
-        } else {
-            AgentDecision::None
+            records.push(AgentDecisionRecord::new(agent_id, decision));
+        }
+        Ok((modified_content, records))
+    }
+
+    /// Collect what agents need to know about a message: the sender, the chat and the
+    /// recent messages before it (`before_id`, or the latest ones if not given). For a
+    /// message in a thread, the history is the thread.
+    pub(crate) async fn build_agent_context(
+        &self,
+        chat_id: u64,
+        sender_id: u64,
+        before_id: Option<u64>,
+        thread_root_id: Option<i64>,
+    ) -> Result<AgentContext, AppError> {
+        let chat = self.get_chat_by_id(chat_id).await?;
+        let members = match &chat {
+            Some(chat) => self.fetch_chat_user_by_ids(&chat.members).await?,
+            None => vec![],
         };
+        let sender = members.iter().find(|u| u.id == sender_id as i64).cloned();
 
-        let modified_content = match decision {
-            AgentDecision::Modify(ref s) => Some(s),
-            _ => None,
+        let history_size = self.config.agent.history_size;
+        let before_id = before_id.map(|id| id as i64).unwrap_or(i64::MAX);
+        let mut history = match (history_size, thread_root_id) {
+            (0, _) => vec![],
+            (_, Some(root_id)) => {
+                sqlx::query_as(
+                    r#"
+                    SELECT id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+                        reply_to, thread_root_id, reply_count, mentions, agent_mentions
+                    FROM messages
+                    WHERE (id = $1 OR thread_root_id = $1) AND id < $2 AND deleted_at IS NULL
+                    ORDER BY id DESC
+                    LIMIT $3
+                    "#,
+                )
+                .bind(root_id)
+                .bind(before_id)
+                .bind(history_size as i64)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            (_, None) => {
+                let input = ListMessages {
+                    before: Some(before_id as _),
+                    limit: history_size,
+                    ..Default::default()
+                };
+                self.list_messages(input, chat_id).await?.messages
+            }
         };
+        // messages are queried latest first
+        history.reverse();
+
+        Ok(AgentContext {
+            sender,
+            chat,
+            members,
+            history,
+        })
+    }
+
+    pub async fn get_message_by_id(&self, id: u64) -> Result<Option<Message>, AppError> {
+        let message = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+                reply_to, thread_root_id, reply_count, mentions, agent_mentions
+            FROM messages
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(message)
+    }
+
+    /// Edit a message by its author, the previous version is kept in `message_edits`. Proxy
+    /// agents run again on the new content.
+    pub async fn edit_message(
+        &self,
+        chat_id: u64,
+        id: u64,
+        input: EditMessage,
+        user_id: u64,
+    ) -> Result<Message, AppError> {
+        if input.content.is_empty() {
+            return Err(AppError::UpdateMessageError(
+                "Content cannot be empty".to_string(),
+            ));
+        }
+
+        // checked again under the lock below, but agents shouldn't run for edits bound to fail
+        let message = self
+            .get_message_by_id(id)
+            .await?
+            .filter(|m| m.chat_id == chat_id as i64);
+        let message = verify_author(message, id, user_id)?;
+
+        let ctx = self
+            .build_agent_context(chat_id, user_id, Some(id), message.thread_root_id)
+            .await?;
+        let agents = self.list_agents(chat_id).await?;
+        let mentions = Mentions::parse(&input.content, user_id as _, &ctx.members, &agents);
+        // replies of proxies are not sent again for an edit
+        let proxies = agents
+            .into_iter()
+            .filter(|a| a.r#type == AgentType::Proxy)
+            .filter(|a| !a.args.on_mention || mentions.agents.contains(&a.id))
+            .collect();
+        let (modified_content, records) =
+            self.run_proxy_agents(proxies, &input.content, &ctx).await?;
+        if let Some(agent_id) = deleted_by(&records) {
+            self.record_agent_decisions(chat_id, Some(message.id), &records)
+                .await?;
+            return Err(AppError::UpdateMessageError(format!(
+                "Message is deleted by agent {agent_id}"
+            )));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let message = self
+            .lock_message_for_author(&mut tx, chat_id, id, user_id)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO message_edits (message_id, content, modified_content)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(message.id)
+        .bind(&message.content)
+        .bind(&message.modified_content)
+        .execute(&mut *tx)
+        .await?;
 
-        // create message
         let message: Message = sqlx::query_as(
             r#"
-          INSERT INTO messages (chat_id, sender_id, content, modified_content, files)
-          VALUES ($1, $2, $3, $4, $5)
-          RETURNING *
-          "#,
+            UPDATE messages
+            SET content = $1, modified_content = $5, edited_at = now(), mentions = $3,
+                agent_mentions = $4
+            WHERE id = $2
+            RETURNING id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+                reply_to, thread_root_id, reply_count, mentions, agent_mentions
+            "#,
         )
-        .bind(chat_id as i64)
-        .bind(user_id as i64)
         .bind(input.content)
+        .bind(message.id)
+        .bind(&mentions.users)
+        .bind(&mentions.agents)
         .bind(modified_content)
-        .bind(&input.files)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
+        tx.commit().await?;
 
-        // if decision is reply, create a new message
-        if let AgentDecision::Reply(reply) = decision {
-            let chat = self
-                .get_chat_by_id(chat_id)
-                .await?
-                .expect("chat should exist");
-            if chat.r#type != ChatType::Single {
-                warn!(
-                    "reply decision found in non single chat {}. reply: {}",
-                    chat_id, reply
-                );
-            }
-            let other_user_id = chat
-                .members
-                .into_iter()
-                .find(|m| m != &(user_id as i64))
-                .expect("other user should exist");
-            let _: (i64,) = sqlx::query_as(
-                r#"
-              INSERT INTO messages (chat_id, sender_id, content)
-              VALUES ($1, $2, $3)
-              RETURNING id
-              "#,
+        self.record_agent_decisions(chat_id, Some(message.id), &records)
+            .await?;
+        Ok(message)
+    }
+
+    /// Soft delete a message by its author. It won't show up in the chat any more.
+    pub async fn delete_message(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let message = self
+            .lock_message_for_author(&mut tx, chat_id, id, user_id)
+            .await?;
+
+        sqlx::query("UPDATE messages SET deleted_at = now() WHERE id = $1")
+            .bind(message.id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(root_id) = message.thread_root_id {
+            update_reply_count(&mut tx, root_id, -1).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// List previous versions of a message, oldest first
+    pub async fn list_message_edits(
+        &self,
+        chat_id: u64,
+        id: u64,
+    ) -> Result<Vec<MessageEdit>, AppError> {
+        let edits = sqlx::query_as(
+            r#"
+            SELECT e.id, e.message_id, e.content, e.modified_content, e.created_at
+            FROM message_edits e
+            JOIN messages m ON m.id = e.message_id
+            WHERE m.chat_id = $1 AND m.id = $2 AND m.deleted_at IS NULL
+            ORDER BY e.id ASC
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(edits)
+    }
+
+    // load a message for change and make sure the user is its author
+    async fn lock_message_for_author(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+    ) -> Result<Message, AppError> {
+        let message: Option<Message> = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+                reply_to, thread_root_id, reply_count, mentions, agent_mentions
+            FROM messages
+            WHERE chat_id = $1 AND id = $2 AND deleted_at IS NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(id as i64)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        verify_author(message, id, user_id)
+    }
+
+    async fn update_message_content(&self, id: i64, content: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE messages SET content = $1 WHERE id = $2")
+            .bind(content)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // agent replies are sent on behalf of the bot a single chat is with, otherwise by the
+    // bot user of the agent, so they never show up under the name of a real user
+    async fn get_reply_sender(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        agent_id: i64,
+    ) -> Result<i64, AppError> {
+        let Some(chat) = self.get_chat_by_id(chat_id).await? else {
+            return Err(AppError::NotFound(format!("chat id {chat_id}")));
+        };
+        if chat.r#type == ChatType::Single {
+            let bot_id: Option<i64> = sqlx::query_scalar(
+                "SELECT id FROM users WHERE id = ANY($1) AND id != $2 AND is_bot",
             )
-            .bind(chat_id as i64)
-            .bind(other_user_id)
-            .bind(reply)
-            .fetch_one(&self.pool)
+            .bind(&chat.members)
+            .bind(user_id as i64)
+            .fetch_optional(&self.pool)
             .await?;
+            if let Some(bot_id) = bot_id {
+                return Ok(bot_id);
+            }
         }
+        self.get_agent_bot_user(agent_id).await
+    }
 
-        Ok(message)
+    // the bot user of an agent is created along with it
+    async fn get_agent_bot_user(&self, agent_id: i64) -> Result<i64, AppError> {
+        let Some(agent) = self.get_agent_by_id(agent_id as _).await? else {
+            return Err(AppError::NotFound(format!("agent id {agent_id}")));
+        };
+        agent
+            .bot_user_id
+            .ok_or_else(|| AppError::NotFound(format!("bot user of agent {agent_id}")))
     }
 
     pub async fn list_messages(
         &self,
         input: ListMessages,
         chat_id: u64,
-    ) -> Result<Vec<Message>, AppError> {
-        let last_id = input.last_id.unwrap_or(i64::MAX as _);
+    ) -> Result<MessagePage, AppError> {
+        let cursors = [input.before, input.after, input.around];
+        if cursors.iter().flatten().count() > 1 {
+            return Err(AppError::ListMessagesError(
+                "only one of `before`, `after` and `around` could be given".to_string(),
+            ));
+        }
         let limit = match input.limit {
-            0 => i64::MAX,
-            1..=100 => input.limit as _,
-            _ => 100,
+            0 => DEFAULT_PAGE_SIZE,
+            1..=MAX_PAGE_SIZE => input.limit,
+            _ => MAX_PAGE_SIZE,
+        };
+
+        let mut page = match (input.before, input.after, input.around) {
+            (_, Some(after), _) => {
+                let (messages, more) = self.fetch_page(chat_id, after, false, limit).await?;
+                let prev = match messages.last() {
+                    Some(m) => self.has_messages_before(chat_id, m.id).await?,
+                    None => None,
+                };
+                let next = more.then(|| messages[0].id);
+                MessagePage {
+                    messages,
+                    prev,
+                    next,
+                }
+            }
+            (_, _, Some(around)) => {
+                let anchor = match self.get_message_by_id(around).await? {
+                    Some(m) if m.chat_id == chat_id as i64 => m,
+                    _ => {
+                        return Err(AppError::NotFound(format!(
+                            "message id {around} in chat {chat_id}"
+                        )))
+                    }
+                };
+                // pages only have top level messages, a thread reply is found by its root
+                let around = anchor.thread_root_id.unwrap_or(anchor.id) as u64;
+                // the anchor goes with the older half
+                let (newer, newer_more) =
+                    self.fetch_page(chat_id, around, false, limit / 2).await?;
+                let (older, older_more) = self
+                    .fetch_page(chat_id, around + 1, true, limit - limit / 2)
+                    .await?;
+                // the newer half is empty for a page of 1
+                let next = match (newer.first(), older.first()) {
+                    (Some(m), _) => newer_more.then_some(m.id),
+                    (None, Some(m)) => self.has_messages_after(chat_id, m.id).await?,
+                    (None, None) => None,
+                };
+                let prev = older_more.then(|| older[older.len() - 1].id);
+                let mut messages = newer;
+                messages.extend(older);
+                MessagePage {
+                    messages,
+                    prev,
+                    next,
+                }
+            }
+            (before, _, _) => {
+                let cursor = before.unwrap_or(i64::MAX as _);
+                let (messages, more) = self.fetch_page(chat_id, cursor, true, limit).await?;
+                let next = match (before, messages.first()) {
+                    (Some(_), Some(m)) => self.has_messages_after(chat_id, m.id).await?,
+                    _ => None,
+                };
+                let prev = more.then(|| messages[messages.len() - 1].id);
+                MessagePage {
+                    messages,
+                    prev,
+                    next,
+                }
+            }
         };
+        self.attach_reactions(&mut page.messages).await?;
 
-        let messages: Vec<Message> = sqlx::query_as(
+        Ok(page)
+    }
+
+    // fetch up to `limit` top level messages older (or newer) than `cursor`, latest first,
+    // and whether there are more beyond them
+    async fn fetch_page(
+        &self,
+        chat_id: u64,
+        cursor: u64,
+        older: bool,
+        limit: u64,
+    ) -> Result<(Vec<Message>, bool), AppError> {
+        if limit == 0 {
+            return Ok((vec![], false));
+        }
+        let (cond, order) = if older {
+            ("id < $2", "DESC")
+        } else {
+            ("id > $2", "ASC")
+        };
+        let sql = format!(
             r#"
-        SELECT id, chat_id, sender_id, content, modified_content, files, created_at
+        SELECT id, chat_id, sender_id, content, modified_content, files, created_at, edited_at,
+            reply_to, thread_root_id, reply_count, mentions, agent_mentions
         FROM messages
         WHERE chat_id = $1
-        AND id < $2
-        ORDER BY id DESC
+        AND {cond}
+        AND deleted_at IS NULL
+        AND thread_root_id IS NULL
+        ORDER BY id {order}
         LIMIT $3
+        "#
+        );
+        let mut messages: Vec<Message> = sqlx::query_as(&sql)
+            .bind(chat_id as i64)
+            .bind(cursor.min(i64::MAX as _) as i64)
+            .bind(limit as i64 + 1)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let more = messages.len() as u64 > limit;
+        messages.truncate(limit as _);
+        if !older {
+            messages.reverse();
+        }
+        Ok((messages, more))
+    }
+
+    // the cursor to load older messages than `id`, if there are any
+    async fn has_messages_before(&self, chat_id: u64, id: i64) -> Result<Option<i64>, AppError> {
+        let (exists,): (bool,) = sqlx::query_as(
+            r#"
+        SELECT EXISTS(
+            SELECT 1 FROM messages
+            WHERE chat_id = $1 AND id < $2 AND deleted_at IS NULL AND thread_root_id IS NULL
+        )
         "#,
         )
         .bind(chat_id as i64)
-        .bind(last_id as i64)
-        .bind(limit)
-        .fetch_all(&self.pool)
+        .bind(id)
+        .fetch_one(&self.pool)
         .await?;
+        Ok(exists.then_some(id))
+    }
 
-        Ok(messages)
+    // the cursor to load newer messages than `id`, if there are any
+    async fn has_messages_after(&self, chat_id: u64, id: i64) -> Result<Option<i64>, AppError> {
+        let (exists,): (bool,) = sqlx::query_as(
+            r#"
+        SELECT EXISTS(
+            SELECT 1 FROM messages
+            WHERE chat_id = $1 AND id > $2 AND deleted_at IS NULL AND thread_root_id IS NULL
+        )
+        "#,
+        )
+        .bind(chat_id as i64)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(exists.then_some(id))
+    }
+}
+
+impl AgentDecisionRecord {
+    pub(crate) fn new(agent_id: i64, decision: AgentDecision) -> Self {
+        Self {
+            agent_id,
+            decision,
+            reply_id: None,
+        }
+    }
+
+    fn with_reply(mut self, reply_id: i64) -> Self {
+        self.reply_id = Some(reply_id);
+        self
+    }
+}
+
+// the root message is updated as well, so clients get the new count. Done in the transaction
+// adding or removing the reply, so the count can't drift.
+async fn update_reply_count(
+    tx: &mut Transaction<'_, Postgres>,
+    root_id: i64,
+    delta: i32,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE messages SET reply_count = reply_count + $1 WHERE id = $2")
+        .bind(delta)
+        .bind(root_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+// the agent which deleted the message, see `run_proxy_agents`
+fn deleted_by(records: &[AgentDecisionRecord]) -> Option<i64> {
+    records
+        .last()
+        .filter(|r| matches!(r.decision, AgentDecision::Delete))
+        .map(|r| r.agent_id)
+}
+
+fn verify_author(message: Option<Message>, id: u64, user_id: u64) -> Result<Message, AppError> {
+    match message {
+        Some(message) if message.sender_id == user_id as i64 => Ok(message),
+        Some(_) => Err(AppError::PermissionDenied(format!(
+            "user {user_id} is not the author of message {id}"
+        ))),
+        None => Err(AppError::NotFound(format!("message id {id}"))),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::CreateAgent;
     use anyhow::Result;
+    use chat_core::{AdapterType, AgentArgs};
 
     #[tokio::test]
     async fn create_message_should_work() -> Result<()> {
@@ -161,6 +939,8 @@ mod tests {
         let input = CreateMessage {
             content: "hello".to_string(),
             files: vec![],
+            reply_to: None,
+            send_at: None,
         };
         let message = state
             .create_message(input, 1, 1)
@@ -172,6 +952,8 @@ mod tests {
         let input = CreateMessage {
             content: "hello".to_string(),
             files: vec!["1".to_string()],
+            reply_to: None,
+            send_at: None,
         };
 
         let err = state.create_message(input, 1, 1).await.unwrap_err();
@@ -182,6 +964,8 @@ mod tests {
         let input = CreateMessage {
             content: "hello".to_string(),
             files: vec![url],
+            reply_to: None,
+            send_at: None,
         };
 
         let message = state
@@ -194,27 +978,318 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn create_message_should_run_all_agents() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // chat 1 already has a proxy agent, add another proxy and a tap agent
+        for (name, r#type) in [
+            ("moderation", AgentType::Proxy),
+            ("archive", AgentType::Tap),
+        ] {
+            let input = CreateAgent::new(
+                name,
+                r#type,
+                AdapterType::Test,
+                "gpt-4o",
+                "You are a helpful assistant",
+                AgentArgs::default(),
+            );
+            state.create_agent(input, 1).await?;
+        }
+
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        let message = state.create_message(input, 1, 1).await?;
+        assert_eq!(message.modified_content.as_deref(), Some("test"));
+
+        let decisions: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT agent_id, decision::text FROM agent_decisions WHERE message_id = $1 ORDER BY id",
+        )
+        .bind(message.id)
+        .fetch_all(&state.pool)
+        .await?;
+        assert_eq!(decisions.len(), 2);
+        assert!(decisions.iter().all(|(_, d)| d == "modify"));
+
+        // tap agent is queued
+        let job = state.claim_agent_job().await?.expect("job should exist");
+        assert_eq!(job.message_id, message.id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn agent_replies_should_be_sent_by_bot_user() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateAgent::new(
+            "faq",
+            AgentType::Reply,
+            AdapterType::Test,
+            "gpt-4o",
+            "You are a helpful assistant",
+            AgentArgs::default(),
+        );
+        let agent = state.create_agent(input, 1).await?;
+        let bot_user_id = agent.bot_user_id.expect("bot user should be created");
+        let count_users = "SELECT count(*) FROM users";
+        let users: i64 = sqlx::query_scalar(count_users)
+            .fetch_one(&state.pool)
+            .await?;
+
+        // chat 1 is a public channel
+        assert_eq!(state.get_reply_sender(1, 1, agent.id).await?, bot_user_id);
+        // single chat 3 has no bot member
+        assert_eq!(state.get_reply_sender(3, 1, agent.id).await?, bot_user_id);
+        // replying doesn't create users
+        let after: i64 = sqlx::query_scalar(count_users)
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(after, users);
+
+        // the fixture agent was created without a bot user
+        let err = state.get_reply_sender(1, 1, 1).await.unwrap_err();
+        assert_eq!(err.to_string(), "Not found: bot user of agent 1");
+        let err = state.get_reply_sender(1, 1, 100).await.unwrap_err();
+        assert_eq!(err.to_string(), "Not found: agent id 100");
+        let err = state.get_reply_sender(100, 1, agent.id).await.unwrap_err();
+        assert_eq!(err.to_string(), "Not found: chat id 100");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn list_messages_should_work() -> Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
         let input = ListMessages {
-            last_id: None,
             limit: 6,
+            ..Default::default()
         };
 
-        let messages = state.list_messages(input, 1).await?;
-        assert_eq!(messages.len(), 6);
-
-        let last_id = messages.last().expect("last message should exists").id;
+        let page = state.list_messages(input, 1).await?;
+        assert_eq!(page.messages.len(), 6);
+        assert_eq!(page.next, None);
+        let prev = page.prev.expect("should have older messages");
 
         let input = ListMessages {
-            last_id: Some(last_id as _),
+            before: Some(prev as _),
             limit: 6,
+            ..Default::default()
+        };
+
+        let page = state.list_messages(input, 1).await?;
+        assert_eq!(page.messages.len(), 4);
+        assert_eq!(page.prev, None);
+        let next = page.next.expect("should have newer messages");
+
+        let input = ListMessages {
+            after: Some(next as _),
+            limit: 3,
+            ..Default::default()
+        };
+        let page = state.list_messages(input, 1).await?;
+        let ids: Vec<_> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [7, 6, 5]);
+        assert_eq!(page.prev, Some(5));
+        assert_eq!(page.next, Some(7));
+
+        let input = ListMessages {
+            before: Some(5),
+            after: Some(3),
+            ..Default::default()
+        };
+        let err = state.list_messages(input, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::ListMessagesError(_)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_messages_around_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = ListMessages {
+            around: Some(5),
+            limit: 4,
+            ..Default::default()
+        };
+        let page = state.list_messages(input, 1).await?;
+        let ids: Vec<_> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [7, 6, 5, 4]);
+        assert_eq!(page.prev, Some(4));
+        assert_eq!(page.next, Some(7));
+
+        // near the end there's nothing newer
+        let input = ListMessages {
+            around: Some(10),
+            limit: 4,
+            ..Default::default()
+        };
+        let page = state.list_messages(input, 1).await?;
+        let ids: Vec<_> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [10, 9]);
+        assert_eq!(page.next, None);
+
+        // a page of 1 is the anchor alone
+        let input = ListMessages {
+            around: Some(5),
+            limit: 1,
+            ..Default::default()
+        };
+        let page = state.list_messages(input, 1).await?;
+        let ids: Vec<_> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [5]);
+        assert_eq!(page.prev, Some(5));
+        assert_eq!(page.next, Some(5));
+
+        // a thread reply is found by its root
+        let input = CreateMessage {
+            content: "reply".to_string(),
+            files: vec![],
+            reply_to: Some(3),
+            send_at: None,
+        };
+        let reply = state.create_message(input, 1, 2).await?;
+        let input = ListMessages {
+            around: Some(reply.id as _),
+            limit: 2,
+            ..Default::default()
+        };
+        let page = state.list_messages(input, 1).await?;
+        let ids: Vec<_> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [4, 3]);
+
+        // message 1 isn't in chat 2
+        let input = ListMessages {
+            around: Some(1),
+            ..Default::default()
+        };
+        let err = state.list_messages(input, 2).await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn edit_message_should_keep_history() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = EditMessage {
+            content: "Hello, everyone!".to_string(),
+        };
+        let message = state.edit_message(1, 1, input, 1).await?;
+        assert_eq!(message.content, "Hello, everyone!");
+        assert!(message.edited_at.is_some());
+        // the proxy agent of chat 1 runs on the new content
+        assert_eq!(message.modified_content.as_deref(), Some("test"));
+
+        let input = EditMessage {
+            content: "Hello, all!".to_string(),
+        };
+        state.edit_message(1, 1, input, 1).await?;
+
+        let edits = state.list_message_edits(1, 1).await?;
+        let contents: Vec<_> = edits.iter().map(|e| e.content.as_str()).collect();
+        assert_eq!(contents, ["Hello, world!", "Hello, everyone!"]);
+
+        // only the author could edit
+        let input = EditMessage {
+            content: "hacked".to_string(),
+        };
+        let err = state.edit_message(1, 1, input, 2).await.unwrap_err();
+        assert!(matches!(err, AppError::PermissionDenied(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_message_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // message 2 is sent by user 2
+        let err = state.delete_message(1, 2, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::PermissionDenied(_)));
+
+        state.delete_message(1, 1, 1).await?;
+        assert!(state.get_message_by_id(1).await?.is_none());
+        let page = state.list_messages(ListMessages::default(), 1).await?;
+        assert_eq!(page.messages.len(), 9);
+
+        // deleted message can't be edited or deleted again
+        let err = state.delete_message(1, 1, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn thread_messages_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateMessage {
+            content: "replying to you".to_string(),
+            files: vec![],
+            reply_to: Some(2),
+            send_at: None,
         };
+        let reply = state.create_message(input, 1, 1).await?;
+        assert_eq!(reply.reply_to, Some(2));
+        assert_eq!(reply.thread_root_id, Some(2));
+
+        // reply to a reply goes into the same thread
+        let input = CreateMessage {
+            content: "me too".to_string(),
+            files: vec![],
+            reply_to: Some(reply.id as _),
+            send_at: None,
+        };
+        let reply2 = state.create_message(input, 1, 3).await?;
+        assert_eq!(reply2.thread_root_id, Some(2));
+
+        let root = state
+            .get_message_by_id(2)
+            .await?
+            .expect("root should exist");
+        assert_eq!(root.reply_count, 2);
+
+        let thread = state.list_thread_messages(1, reply.id as _).await?;
+        let ids: Vec<_> = thread.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [2, reply.id, reply2.id]);
 
-        let messages = state.list_messages(input, 1).await?;
-        assert_eq!(messages.len(), 4);
+        // thread replies don't show up at top level
+        let page = state.list_messages(ListMessages::default(), 1).await?;
+        assert_eq!(page.messages.len(), 10);
 
+        // agents in a thread see the thread as history
+        let ctx = state
+            .build_agent_context(1, 3, Some(reply2.id as _), Some(2))
+            .await?;
+        let ids: Vec<_> = ctx.history.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [2, reply.id]);
+
+        state.delete_message(1, reply2.id as _, 3).await?;
+        let root = state
+            .get_message_by_id(2)
+            .await?
+            .expect("root should exist");
+        assert_eq!(root.reply_count, 1);
+
+        // message to reply must be in the same chat
+        let input = CreateMessage {
+            content: "hi".to_string(),
+            files: vec![],
+            reply_to: Some(2),
+            send_at: None,
+        };
+        let err = state.create_message(input, 2, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::CreateMessageError(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn build_agent_context_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ctx = state.build_agent_context(1, 1, Some(10), None).await?;
+        assert_eq!(ctx.sender.expect("sender should exist").id, 1);
+        assert_eq!(ctx.chat.expect("chat should exist").id, 1);
+        assert_eq!(ctx.members.len(), 5);
+        // oldest first, before message 10
+        let ids: Vec<_> = ctx.history.iter().map(|m| m.id).collect();
+        assert_eq!(ids, (1..10).collect::<Vec<_>>());
         Ok(())
     }
 
@@ -1,13 +1,28 @@
 mod agent;
+mod bookmark;
 mod chat;
 mod file;
+mod job;
+mod mention;
 mod messages;
+mod pin;
+mod reaction;
+mod scheduled;
+mod search;
 mod user;
 mod workspace;
 
 pub use agent::{CreateAgent, UpdateAgent};
-pub use chat::CreateChat;
-pub use messages::{CreateMessage, ListMessages};
+pub use bookmark::Bookmark;
+pub use chat::{CreateChat, MarkRead, UpdateChat};
+pub use job::{AgentJob, AgentJobStatus};
+pub(crate) use mention::Mentions;
+pub(crate) use messages::AgentDecisionRecord;
+pub use messages::{CreateMessage, EditMessage, ListMessages, MessageEdit, MessagePage};
+pub use pin::PinnedMessage;
+pub use scheduled::{ScheduledMessage, ScheduledMessageStatus};
+pub(crate) use search::build_embedding_adapter;
+pub use search::{FindSimilar, SearchHit, SearchMessages, SimilarHit};
 use serde::{Deserialize, Serialize};
 pub use user::{CreateUser, SigninUser};
 
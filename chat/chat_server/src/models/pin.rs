@@ -0,0 +1,150 @@
+use crate::{AppError, AppState};
+use chat_core::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct PinnedMessage {
+    #[sqlx(flatten)]
+    pub message: Message,
+    pub pinned_by: i64,
+    pub pinned_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Pin a message in its chat, pinning it twice is a no-op. Returns the pinned messages
+    /// of the chat.
+    pub async fn pin_message(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+    ) -> Result<Vec<PinnedMessage>, AppError> {
+        self.verify_message_in_chat(chat_id, id).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO message_pins (message_id, chat_id, pinned_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(id as i64)
+        .bind(chat_id as i64)
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+
+        self.list_pins(chat_id).await
+    }
+
+    /// Unpin a message, returns the pinned messages left in the chat. Only the user who
+    /// pinned it, the chat creator or workspace owner could do it.
+    pub async fn unpin_message(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+    ) -> Result<Vec<PinnedMessage>, AppError> {
+        self.verify_message_in_chat(chat_id, id).await?;
+
+        let row: Option<(i64, Option<i64>, i64)> = sqlx::query_as(
+            r#"
+            SELECT p.pinned_by, c.created_by, w.owner_id
+            FROM message_pins p
+            JOIN chats c ON c.id = p.chat_id
+            JOIN workspaces w ON w.id = c.ws_id
+            WHERE p.message_id = $1 AND p.chat_id = $2
+            "#,
+        )
+        .bind(id as i64)
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        // unpinning a message not pinned is a no-op
+        if let Some((pinned_by, created_by, owner_id)) = row {
+            let user_id = user_id as i64;
+            if pinned_by != user_id && created_by != Some(user_id) && owner_id != user_id {
+                return Err(AppError::PermissionDenied(format!(
+                    "only the pinner, chat creator or workspace owner could unpin message {id}"
+                )));
+            }
+
+            // the trigger reports who unpinned the message from `chat.actor_id`
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("SELECT set_config('chat.actor_id', $1, true)")
+                .bind(user_id.to_string())
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM message_pins WHERE message_id = $1 AND chat_id = $2")
+                .bind(id as i64)
+                .bind(chat_id as i64)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+
+        self.list_pins(chat_id).await
+    }
+
+    /// Pinned messages of the chat, latest pinned first
+    pub async fn list_pins(&self, chat_id: u64) -> Result<Vec<PinnedMessage>, AppError> {
+        let pins = sqlx::query_as(
+            r#"
+            SELECT m.id, m.chat_id, m.sender_id, m.content, m.modified_content, m.files,
+                m.created_at, m.edited_at, m.reply_to, m.thread_root_id, m.reply_count, m.mentions,
+                m.agent_mentions, p.pinned_by, p.created_at AS pinned_at
+            FROM message_pins p
+            JOIN messages m ON m.id = p.message_id
+            WHERE p.chat_id = $1 AND m.deleted_at IS NULL
+            ORDER BY p.created_at DESC, p.message_id DESC
+            "#,
+        )
+        .bind(chat_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn pin_message_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.pin_message(1, 3, 2).await?;
+        let pins = state.pin_message(1, 5, 1).await?;
+        let ids: Vec<_> = pins.iter().map(|p| p.message.id).collect();
+        assert_eq!(ids, [5, 3]);
+        assert_eq!(pins[1].pinned_by, 2);
+
+        // pinning twice is a no-op
+        assert_eq!(state.pin_message(1, 5, 3).await?.len(), 2);
+
+        let chat = state.get_chat_by_id(1).await?.expect("chat should exist");
+        assert_eq!(chat.pins, [5, 3]);
+
+        // only the user who pinned it, chat creator or workspace owner could unpin
+        let err = state.unpin_message(1, 3, 3).await.unwrap_err();
+        assert!(matches!(err, AppError::PermissionDenied(_)));
+
+        let pins = state.unpin_message(1, 5, 1).await?;
+        assert_eq!(pins.len(), 1);
+        let chats = state.fetch_chats(1, 1).await?;
+        let chat = chats.iter().find(|c| c.id == 1).expect("chat should exist");
+        assert_eq!(chat.pins, [3]);
+
+        // message 1 is in chat 1
+        let err = state.pin_message(2, 1, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+}
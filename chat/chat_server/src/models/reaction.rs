@@ -0,0 +1,200 @@
+use crate::{AppError, AppState};
+use chat_core::{Message, ReactionCount};
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+// same as the column size of `message_reactions.emoji`
+const MAX_EMOJI_LEN: usize = 32;
+
+#[derive(Debug, FromRow)]
+struct MessageReactionCount {
+    message_id: i64,
+    #[sqlx(flatten)]
+    reaction: ReactionCount,
+}
+
+impl AppState {
+    /// Add a reaction to a message, reacting twice with the same emoji is a no-op.
+    /// Returns the aggregated reactions of the message.
+    pub async fn add_reaction(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+        emoji: &str,
+    ) -> Result<Vec<ReactionCount>, AppError> {
+        validate_emoji(emoji)?;
+        self.verify_message_in_chat(chat_id, id).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO message_reactions (message_id, user_id, emoji)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .bind(emoji)
+        .execute(&self.pool)
+        .await?;
+
+        self.list_reactions(id).await
+    }
+
+    /// Remove the user's reaction from a message, returns the aggregated reactions left.
+    pub async fn remove_reaction(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+        emoji: &str,
+    ) -> Result<Vec<ReactionCount>, AppError> {
+        self.verify_message_in_chat(chat_id, id).await?;
+
+        sqlx::query(
+            "DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .bind(emoji)
+        .execute(&self.pool)
+        .await?;
+
+        self.list_reactions(id).await
+    }
+
+    pub async fn list_reactions(&self, id: u64) -> Result<Vec<ReactionCount>, AppError> {
+        let mut reactions = self.load_reactions(&[id as i64]).await?;
+        Ok(reactions.remove(&(id as i64)).unwrap_or_default())
+    }
+
+    /// Fill in the aggregated reactions of the messages with one query
+    pub(crate) async fn attach_reactions(&self, messages: &mut [Message]) -> Result<(), AppError> {
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        let mut reactions = self.load_reactions(&ids).await?;
+        for message in messages.iter_mut() {
+            message.reactions = reactions.remove(&message.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    // reactions of each message, emojis are ordered by their first use
+    async fn load_reactions(
+        &self,
+        ids: &[i64],
+    ) -> Result<HashMap<i64, Vec<ReactionCount>>, AppError> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows: Vec<MessageReactionCount> = sqlx::query_as(
+            r#"
+            SELECT message_id, emoji, count(*) AS count,
+                array_agg(user_id ORDER BY created_at, user_id) AS user_ids
+            FROM message_reactions
+            WHERE message_id = ANY($1)
+            GROUP BY message_id, emoji
+            ORDER BY message_id, min(created_at), emoji
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reactions: HashMap<i64, Vec<ReactionCount>> = HashMap::new();
+        for row in rows {
+            reactions
+                .entry(row.message_id)
+                .or_default()
+                .push(row.reaction);
+        }
+        Ok(reactions)
+    }
+
+    pub(crate) async fn verify_message_in_chat(
+        &self,
+        chat_id: u64,
+        id: u64,
+    ) -> Result<(), AppError> {
+        match self.get_message_by_id(id).await? {
+            Some(msg) if msg.chat_id == chat_id as i64 => Ok(()),
+            _ => Err(AppError::NotFound(format!("message id {id}"))),
+        }
+    }
+}
+
+fn validate_emoji(emoji: &str) -> Result<(), AppError> {
+    if emoji.is_empty() {
+        return Err(AppError::ReactionError("Emoji cannot be empty".to_string()));
+    }
+    if emoji.chars().count() > MAX_EMOJI_LEN || emoji.chars().any(char::is_whitespace) {
+        return Err(AppError::ReactionError(format!("Invalid emoji: {emoji}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ListMessages;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn add_and_remove_reaction_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.add_reaction(1, 1, 1, "👍").await?;
+        state.add_reaction(1, 1, 2, "👍").await?;
+        // reacting twice is ignored
+        state.add_reaction(1, 1, 2, "👍").await?;
+        let reactions = state.add_reaction(1, 1, 3, "🎉").await?;
+
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].count, 2);
+        assert_eq!(reactions[0].user_ids, vec![1, 2]);
+        assert_eq!(reactions[1].emoji, "🎉");
+        assert_eq!(reactions[1].count, 1);
+
+        let reactions = state.remove_reaction(1, 1, 1, "👍").await?;
+        assert_eq!(reactions[0].count, 1);
+        assert_eq!(reactions[0].user_ids, vec![2]);
+
+        let reactions = state.remove_reaction(1, 1, 3, "🎉").await?;
+        assert_eq!(reactions.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_reaction_should_reject_invalid_input() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let err = state.add_reaction(1, 1, 1, "").await.unwrap_err();
+        assert_eq!(err.to_string(), "reaction error: Emoji cannot be empty");
+
+        let err = state.add_reaction(1, 1, 1, "a b").await.unwrap_err();
+        assert!(matches!(err, AppError::ReactionError(_)));
+
+        // message 1 is in chat 1
+        let err = state.add_reaction(2, 1, 1, "👍").await.unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_messages_should_include_reactions() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.add_reaction(1, 1, 2, "👍").await?;
+        let messages = state
+            .list_messages(ListMessages::default(), 1)
+            .await?
+            .messages;
+        let message = messages.iter().find(|m| m.id == 1).expect("message 1");
+        assert_eq!(message.reactions.len(), 1);
+        assert_eq!(message.reactions[0].user_ids, vec![2]);
+        assert!(messages
+            .iter()
+            .filter(|m| m.id != 1)
+            .all(|m| m.reactions.is_empty()));
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{AppError, AppState, CreateMessage};
+use chat_core::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, PartialEq, ToSchema, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "scheduled_message_status", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduledMessageStatus {
+    Pending,
+    Sending,
+    Sent,
+    Cancelled,
+    Failed,
+}
+
+/// A message to be sent later, stored in `scheduled_messages`.
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ScheduledMessage {
+    pub id: i64,
+    pub chat_id: i64,
+    pub sender_id: i64,
+    pub content: String,
+    pub files: Vec<String>,
+    pub reply_to: Option<i64>,
+    pub send_at: DateTime<Utc>,
+    pub status: ScheduledMessageStatus,
+    /// the message delivered
+    pub message_id: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Store a message to be delivered at its `send_at` by the scheduler. Nothing shows up
+    /// in the chat until then.
+    pub async fn schedule_message(
+        &self,
+        input: CreateMessage,
+        chat_id: u64,
+        user_id: u64,
+    ) -> Result<ScheduledMessage, AppError> {
+        let Some(send_at) = input.send_at else {
+            return Err(AppError::CreateMessageError(
+                "send_at is required to schedule a message".to_string(),
+            ));
+        };
+        self.verify_message_input(&input)?;
+        if let Some(id) = input.reply_to {
+            self.get_thread_root_id(chat_id, id).await?;
+        }
+
+        let scheduled = sqlx::query_as(
+            r#"
+            INSERT INTO scheduled_messages (chat_id, sender_id, content, files, reply_to, send_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(user_id as i64)
+        .bind(input.content)
+        .bind(&input.files)
+        .bind(input.reply_to.map(|id| id as i64))
+        .bind(send_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(scheduled)
+    }
+
+    /// Pending scheduled messages of the user in the chat, the earliest first
+    pub async fn list_scheduled_messages(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<ScheduledMessage>, AppError> {
+        let scheduled = sqlx::query_as(
+            r#"
+            SELECT * FROM scheduled_messages
+            WHERE chat_id = $1 AND sender_id = $2 AND status = 'pending'
+            ORDER BY send_at, id
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(scheduled)
+    }
+
+    /// Cancel a pending scheduled message, only its sender could do it.
+    pub async fn cancel_scheduled_message(
+        &self,
+        chat_id: u64,
+        id: u64,
+        user_id: u64,
+    ) -> Result<ScheduledMessage, AppError> {
+        let scheduled = sqlx::query_as(
+            r#"
+            UPDATE scheduled_messages SET status = 'cancelled', updated_at = now()
+            WHERE id = $1 AND chat_id = $2 AND sender_id = $3 AND status = 'pending'
+            RETURNING *
+            "#,
+        )
+        .bind(id as i64)
+        .bind(chat_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        scheduled.ok_or_else(|| AppError::NotFound(format!("scheduled message id {id}")))
+    }
+
+    /// Claim the next scheduled message which is due. It's leased for the worker timeout, so
+    /// a message claimed by an instance which crashed is claimed again once the lease is over.
+    /// Messages are delivered at most once, as they are marked as sent along with the message.
+    pub async fn claim_scheduled_message(&self) -> Result<Option<ScheduledMessage>, AppError> {
+        let scheduled = sqlx::query_as(
+            r#"
+            UPDATE scheduled_messages
+            SET status = 'sending', locked_until = now() + make_interval(secs => $1),
+                updated_at = now()
+            WHERE id = (
+                SELECT id FROM scheduled_messages
+                WHERE (status = 'pending' AND send_at <= now())
+                OR (status = 'sending' AND locked_until < now())
+                ORDER BY send_at, id
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(self.config.worker.timeout as f64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(scheduled)
+    }
+
+    /// Send a claimed scheduled message as if the user sent it now.
+    pub async fn deliver_scheduled_message(
+        &self,
+        scheduled: &ScheduledMessage,
+    ) -> Result<Message, AppError> {
+        let (chat_id, user_id) = (scheduled.chat_id as u64, scheduled.sender_id as u64);
+        // the user may have left the chat in the meantime
+        if !self.is_chat_member(chat_id, user_id).await? {
+            return Err(AppError::NotChatMemberError { user_id, chat_id });
+        }
+
+        let input = CreateMessage {
+            content: scheduled.content.clone(),
+            files: scheduled.files.clone(),
+            reply_to: scheduled.reply_to.map(|id| id as _),
+            send_at: None,
+        };
+        self.send_message(input, chat_id, user_id, Some(scheduled.id))
+            .await
+    }
+
+    /// Mark a claimed scheduled message as failed, it won't be retried.
+    pub async fn fail_scheduled_message(&self, id: u64, error: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE scheduled_messages
+            SET status = 'failed', last_error = $1, locked_until = NULL, updated_at = now()
+            WHERE id = $2 AND status = 'sending'
+            "#,
+        )
+        .bind(error)
+        .bind(id as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// in the transaction inserting the message, so it's either delivered and sent, or neither.
+// Fails if it has been sent meanwhile by another instance which claimed it after the lease.
+pub(crate) async fn mark_scheduled_message_sent(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i64,
+    message_id: i64,
+) -> Result<(), AppError> {
+    let ret = sqlx::query(
+        r#"
+        UPDATE scheduled_messages
+        SET status = 'sent', message_id = $1, locked_until = NULL, updated_at = now()
+        WHERE id = $2 AND status = 'sending'
+        "#,
+    )
+    .bind(message_id)
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+
+    if ret.rows_affected() == 0 {
+        return Err(AppError::CreateMessageError(format!(
+            "Scheduled message {id} is no longer being sent"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ListMessages;
+    use anyhow::Result;
+    use chrono::Duration;
+
+    fn new_scheduled_input(content: &str, send_at: DateTime<Utc>) -> CreateMessage {
+        CreateMessage {
+            content: content.to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: Some(send_at),
+        }
+    }
+
+    #[tokio::test]
+    async fn scheduled_message_should_be_delivered_when_due() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let send_at = Utc::now() + Duration::hours(1);
+        let scheduled = state
+            .schedule_message(new_scheduled_input("later", send_at), 2, 1)
+            .await?;
+        assert_eq!(scheduled.status, ScheduledMessageStatus::Pending);
+
+        // not in the chat, nor due yet
+        let page = state.list_messages(ListMessages::default(), 2).await?;
+        assert!(page.messages.is_empty());
+        assert!(state.claim_scheduled_message().await?.is_none());
+        let list = state.list_scheduled_messages(2, 1).await?;
+        assert_eq!(list, [scheduled.clone()]);
+        assert!(state.list_scheduled_messages(2, 2).await?.is_empty());
+
+        sqlx::query("UPDATE scheduled_messages SET send_at = now() WHERE id = $1")
+            .bind(scheduled.id)
+            .execute(&state.pool)
+            .await?;
+        let claimed = state
+            .claim_scheduled_message()
+            .await?
+            .expect("scheduled message should be due");
+        assert_eq!(claimed.status, ScheduledMessageStatus::Sending);
+        assert!(state.claim_scheduled_message().await?.is_none());
+
+        let message = state.deliver_scheduled_message(&claimed).await?;
+        assert_eq!(message.content, "later");
+        let page = state.list_messages(ListMessages::default(), 2).await?;
+        assert_eq!(page.messages.len(), 1);
+        assert!(state.list_scheduled_messages(2, 1).await?.is_empty());
+
+        let (status, message_id): (ScheduledMessageStatus, Option<i64>) =
+            sqlx::query_as("SELECT status, message_id FROM scheduled_messages WHERE id = $1")
+                .bind(scheduled.id)
+                .fetch_one(&state.pool)
+                .await?;
+        assert_eq!(status, ScheduledMessageStatus::Sent);
+        assert_eq!(message_id, Some(message.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scheduled_message_with_expired_lease_should_be_delivered_once() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let scheduled = state
+            .schedule_message(new_scheduled_input("later", Utc::now()), 2, 1)
+            .await?;
+        let claimed = state
+            .claim_scheduled_message()
+            .await?
+            .expect("scheduled message should be due");
+
+        // the instance which claimed it is gone
+        sqlx::query("UPDATE scheduled_messages SET locked_until = now() WHERE id = $1")
+            .bind(scheduled.id)
+            .execute(&state.pool)
+            .await?;
+        let reclaimed = state
+            .claim_scheduled_message()
+            .await?
+            .expect("scheduled message should be claimed again");
+        assert_eq!(reclaimed.id, claimed.id);
+
+        state.deliver_scheduled_message(&reclaimed).await?;
+        let err = state.deliver_scheduled_message(&claimed).await.unwrap_err();
+        assert!(matches!(err, AppError::CreateMessageError(_)));
+        let page = state.list_messages(ListMessages::default(), 2).await?;
+        assert_eq!(page.messages.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancel_scheduled_message_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let send_at = Utc::now() + Duration::hours(1);
+        let scheduled = state
+            .schedule_message(new_scheduled_input("later", send_at), 2, 1)
+            .await?;
+
+        // only the sender could cancel it
+        let err = state
+            .cancel_scheduled_message(2, scheduled.id as _, 2)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+
+        let cancelled = state
+            .cancel_scheduled_message(2, scheduled.id as _, 1)
+            .await?;
+        assert_eq!(cancelled.status, ScheduledMessageStatus::Cancelled);
+        assert!(state.list_scheduled_messages(2, 1).await?.is_empty());
+
+        // cancelled messages are never delivered
+        sqlx::query("UPDATE scheduled_messages SET send_at = now() WHERE id = $1")
+            .bind(scheduled.id)
+            .execute(&state.pool)
+            .await?;
+        assert!(state.claim_scheduled_message().await?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{AppConfig, AppError, AppState};
+use ai_sdk::OpenaiAdapter;
+use chat_core::{message_embeddings_table, AdapterType, Message};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_SEARCH_LIMIT: u64 = 20;
+const MAX_SEARCH_LIMIT: u64 = 100;
+const DEFAULT_SIMILAR_LIMIT: u64 = 10;
+const MAX_SIMILAR_LIMIT: u64 = 50;
+// nearest neighbours fetched before dropping deleted messages, as a multiple of the limit
+const SIMILAR_CANDIDATES_FACTOR: u64 = 2;
+
+#[derive(Debug, Clone, Default, IntoParams, ToSchema, Serialize, Deserialize)]
+pub struct SearchMessages {
+    /// search terms, supports web search syntax, e.g. `"exact phrase" -excluded or other`
+    pub q: String,
+    #[serde(default)]
+    pub sender_id: Option<u64>,
+    #[serde(default)]
+    pub chat_id: Option<u64>,
+    /// messages created at or after
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// messages created before
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub has_files: Option<bool>,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct SearchHit {
+    #[sqlx(flatten)]
+    pub message: Message,
+    /// matched terms are wrapped in `<mark></mark>`
+    pub snippet: String,
+    pub rank: f32,
+}
+
+#[derive(Debug, Clone, Default, IntoParams, ToSchema, Serialize, Deserialize)]
+pub struct FindSimilar {
+    /// what to look for, in natural language
+    pub q: String,
+    #[serde(default)]
+    pub chat_id: Option<u64>,
+    #[serde(default)]
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone, FromRow, ToSchema, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct SimilarHit {
+    #[sqlx(flatten)]
+    pub message: Message,
+    /// cosine similarity to the query, higher is closer
+    pub score: f64,
+}
+
+impl AppState {
+    /// Search messages in the chats of the user, best matches first.
+    pub async fn search_messages(
+        &self,
+        input: SearchMessages,
+        user_id: u64,
+        ws_id: u64,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        let q = input.q.trim();
+        if q.is_empty() {
+            return Err(AppError::SearchError(
+                "Search query cannot be empty".to_string(),
+            ));
+        }
+        if let (Some(from), Some(to)) = (input.from, input.to) {
+            if from >= to {
+                return Err(AppError::SearchError(
+                    "`from` must be earlier than `to`".to_string(),
+                ));
+            }
+        }
+        let limit = match input.limit {
+            0 => DEFAULT_SEARCH_LIMIT,
+            1..=MAX_SEARCH_LIMIT => input.limit,
+            _ => MAX_SEARCH_LIMIT,
+        };
+
+        // the tsvector expression must be the same as `messages_content_search_idx`
+        let hits = sqlx::query_as(
+            r#"
+            SELECT m.id, m.chat_id, m.sender_id, m.content, m.modified_content, m.files,
+                m.created_at, m.edited_at, m.reply_to, m.thread_root_id, m.reply_count, m.mentions,
+                m.agent_mentions,
+                ts_headline('simple', m.content || COALESCE(E'\n' || m.modified_content, ''), q,
+                    'StartSel=<mark>, StopSel=</mark>') AS snippet,
+                ts_rank(to_tsvector('simple', m.content || ' ' || COALESCE(m.modified_content, '')), q) AS rank
+            FROM messages m
+            JOIN chats c ON c.id = m.chat_id,
+                websearch_to_tsquery('simple', $1) q
+            WHERE c.ws_id = $2 AND $3 = ANY(c.members)
+            AND to_tsvector('simple', m.content || ' ' || COALESCE(m.modified_content, '')) @@ q
+            AND m.deleted_at IS NULL
+            AND ($4::bigint IS NULL OR m.sender_id = $4)
+            AND ($5::bigint IS NULL OR m.chat_id = $5)
+            AND ($6::timestamptz IS NULL OR m.created_at >= $6)
+            AND ($7::timestamptz IS NULL OR m.created_at < $7)
+            AND ($8::boolean IS NULL OR (cardinality(m.files) > 0) = $8)
+            ORDER BY rank DESC, m.id DESC
+            LIMIT $9 OFFSET $10
+            "#,
+        )
+        .bind(q)
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .bind(input.sender_id.map(|v| v as i64))
+        .bind(input.chat_id.map(|v| v as i64))
+        .bind(input.from)
+        .bind(input.to)
+        .bind(input.has_files)
+        .bind(limit as i64)
+        .bind(input.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hits)
+    }
+
+    /// Find messages close to the query in meaning, using the embeddings written by the
+    /// message indexer. Workspaces not indexed yet have no results.
+    pub async fn find_similar_messages(
+        &self,
+        input: FindSimilar,
+        user_id: u64,
+        ws_id: u64,
+    ) -> Result<Vec<SimilarHit>, AppError> {
+        let q = input.q.trim();
+        if q.is_empty() {
+            return Err(AppError::SearchError(
+                "Search query cannot be empty".to_string(),
+            ));
+        }
+        let limit = match input.limit {
+            0 => DEFAULT_SIMILAR_LIMIT,
+            1..=MAX_SIMILAR_LIMIT => input.limit,
+            _ => MAX_SIMILAR_LIMIT,
+        };
+
+        let table = message_embeddings_table(ws_id as _);
+        let indexed: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(&table)
+            .fetch_one(&self.pool)
+            .await?;
+        if !indexed {
+            return Ok(vec![]);
+        }
+
+        let adapter = build_embedding_adapter(&self.config)?;
+        let embedding = adapter
+            .embed(&self.config.embedding.model, &[q.to_string()])
+            .await
+            .map_err(|e| AppError::AdapterError(e.to_string()))?
+            .pop()
+            .ok_or_else(|| AppError::AdapterError("no embedding returned".to_string()))?;
+
+        // vectors are filtered by the chats of the user within the nearest neighbour scan,
+        // the chat id is the second segment of the path (see `message_embedding_path`).
+        // A few more candidates are fetched since deleted messages are still indexed
+        let sql = format!(
+            r#"
+            WITH user_chats AS (
+                SELECT id FROM chats
+                WHERE ws_id = $3 AND members @> ARRAY[$4::bigint]
+                AND ($5::bigint IS NULL OR id = $5)
+            ),
+            candidates AS (
+                SELECT split_part(path, '/', 3)::bigint AS message_id,
+                    embedding <=> $1::vector AS distance
+                FROM {table}
+                WHERE split_part(path, '/', 2)::bigint IN (SELECT id FROM user_chats)
+                ORDER BY embedding <=> $1::vector
+                LIMIT $2
+            )
+            SELECT m.id, m.chat_id, m.sender_id, m.content, m.modified_content, m.files,
+                m.created_at, m.edited_at, m.reply_to, m.thread_root_id, m.reply_count, m.mentions,
+                m.agent_mentions,
+                (1 - e.distance)::float8 AS score
+            FROM candidates e
+            JOIN messages m ON m.id = e.message_id
+            WHERE m.deleted_at IS NULL
+            ORDER BY e.distance ASC
+            LIMIT $6
+            "#
+        );
+        let hits = sqlx::query_as(&sql)
+            .bind(to_pgvector(&embedding))
+            .bind((limit * SIMILAR_CANDIDATES_FACTOR) as i64)
+            .bind(ws_id as i64)
+            .bind(user_id as i64)
+            .bind(input.chat_id.map(|v| v as i64))
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(hits)
+    }
+}
+
+// also called on startup, so a misconfigured provider fails there rather than on search
+pub(crate) fn build_embedding_adapter(config: &AppConfig) -> Result<OpenaiAdapter, AppError> {
+    let name = &config.embedding.provider;
+    let Some(provider) = config.providers.get(name) else {
+        return Err(AppError::AdapterError(format!(
+            "embedding provider {name} is not configured"
+        )));
+    };
+    if provider.adapter != AdapterType::Openai {
+        return Err(AppError::AdapterError(format!(
+            "embedding provider {name} must be OpenAI compatible"
+        )));
+    }
+    let Some(api_key) = provider.api_key() else {
+        return Err(AppError::AdapterError(format!(
+            "api key for embedding provider {name} is not set"
+        )));
+    };
+    let model = config.embedding.model.clone();
+    Ok(match &provider.base_url {
+        Some(url) => OpenaiAdapter::new_with_host(url, api_key, model),
+        None => OpenaiAdapter::new(api_key, model),
+    })
+}
+
+// pgvector's text format, e.g. `[1,2.5,3]`
+fn to_pgvector(v: &[f32]) -> String {
+    let items: Vec<String> = v.iter().map(|x| x.to_string()).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CreateChat, CreateMessage};
+    use anyhow::Result;
+
+    fn search(q: &str) -> SearchMessages {
+        SearchMessages {
+            q: q.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn search_messages_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let hits = state.search_messages(search("hello"), 1, 1).await?;
+        // messages 1, 6, 9 and 10 in chat 1
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().all(|h| h.message.chat_id == 1));
+        assert_eq!(hits[0].snippet, "<mark>Hello</mark>, world!");
+
+        let input = SearchMessages {
+            sender_id: Some(2),
+            ..search("there")
+        };
+        let hits = state.search_messages(input, 1, 1).await?;
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|h| h.message.sender_id == 2));
+
+        let input = SearchMessages {
+            has_files: Some(true),
+            ..search("hello")
+        };
+        assert!(state.search_messages(input, 1, 1).await?.is_empty());
+
+        let input = SearchMessages {
+            limit: 1,
+            offset: 1,
+            ..search("hello")
+        };
+        assert_eq!(state.search_messages(input, 1, 1).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_messages_should_only_include_member_chats() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // chat 4 is a group of users 1, 3 and 4
+        let input = CreateMessage {
+            content: "secret plan".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        state.create_message(input, 4, 1).await?;
+
+        let hits = state.search_messages(search("secret"), 1, 1).await?;
+        assert_eq!(hits.len(), 1);
+        let hits = state.search_messages(search("secret"), 2, 1).await?;
+        assert!(hits.is_empty());
+
+        // a new chat of user 2 is searchable by them
+        let chat = state
+            .create_chat(CreateChat::new("", &[2, 5], false), 2, 1)
+            .await?;
+        let input = CreateMessage {
+            content: "another secret".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        state.create_message(input, chat.id as _, 2).await?;
+        let hits = state.search_messages(search("secret"), 2, 1).await?;
+        assert_eq!(hits.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn find_similar_messages_should_be_empty_if_not_indexed() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = FindSimilar {
+            q: "where did we discuss the deploy freeze?".to_string(),
+            ..Default::default()
+        };
+        let hits = state.find_similar_messages(input, 1, 1).await?;
+        assert!(hits.is_empty());
+
+        let err = state
+            .find_similar_messages(FindSimilar::default(), 1, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::SearchError(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_adapter_without_api_key_should_fail() -> Result<()> {
+        let mut config = AppConfig::load()?;
+        let provider = config
+            .providers
+            .get_mut(&config.embedding.provider)
+            .expect("embedding provider should be configured");
+        provider.api_key = None;
+        provider.api_key_env = None;
+        let err = build_embedding_adapter(&config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "agent adapter error: api key for embedding provider openai is not set"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn to_pgvector_should_work() {
+        assert_eq!(to_pgvector(&[1.0, 2.5, -0.25]), "[1,2.5,-0.25]");
+        assert_eq!(to_pgvector(&[]), "[]");
+    }
+
+    #[tokio::test]
+    async fn search_messages_with_invalid_input_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let err = state.search_messages(search("  "), 1, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::SearchError(_)));
+
+        let now = Utc::now();
+        let input = SearchMessages {
+            from: Some(now),
+            to: Some(now),
+            ..search("hello")
+        };
+        let err = state.search_messages(input, 1, 1).await.unwrap_err();
+        assert!(matches!(err, AppError::SearchError(_)));
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use super::agent::AGENT_EMAIL_DOMAIN;
 use crate::{AppError, AppState};
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
@@ -59,6 +60,13 @@ impl AppState {
         if user.is_some() {
             return Err(AppError::EmailAlreadyExists(input.email.clone()));
         }
+        // reserved for the bot users of agents
+        if input.email.ends_with(AGENT_EMAIL_DOMAIN) {
+            return Err(AppError::PermissionDenied(format!(
+                "email {} is reserved",
+                input.email
+            )));
+        }
 
         // check if workspace exists, if not create one
         let ws = match self.find_workspace_by_name(&input.workspace).await? {
@@ -134,12 +142,16 @@ impl AppState {
         Ok(users)
     }
 
+    /// List users in the workspace, with their presence reported through notify_server.
     pub async fn fetch_chat_users(&self, ws_id: u64) -> Result<Vec<ChatUser>, AppError> {
         let users = sqlx::query_as(
             r#"
-        SELECT id, fullname, email
-        FROM users
-        WHERE ws_id = $1
+        SELECT u.id, u.fullname, u.email,
+            COALESCE(p.status = 'online' AND p.expires_at > now(), false) AS online
+        FROM users u
+        LEFT JOIN user_presence p ON p.user_id = u.id
+        WHERE u.ws_id = $1
+        ORDER BY u.id
         "#,
         )
         .bind(ws_id as i64)
@@ -226,6 +238,16 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn create_user_with_agent_email_should_fail() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+
+        let input = CreateUser::new("acme", "Agent", "agent-2@agents.invalid", "hunter42");
+        let ret = state.create_user(&input).await;
+        assert!(matches!(ret, Err(AppError::PermissionDenied(_))));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn create_and_verify_user_should_work() -> Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
@@ -110,6 +110,22 @@ mod tests {
         let (_tdb, state) = AppState::new_for_test().await?;
         let users = state.fetch_chat_users(1).await?;
         assert_eq!(users.len(), 5);
+        assert!(users.iter().all(|u| !u.online));
+
+        // presence is reported through notify_server, it's stale after expires_at
+        sqlx::query(
+            r#"
+            INSERT INTO user_presence (user_id, status, expires_at)
+            VALUES (2, 'online', now() + interval '1 minute'),
+                (3, 'away', now() + interval '1 minute'),
+                (4, 'online', now() - interval '1 minute')
+            "#,
+        )
+        .execute(&state.pool)
+        .await?;
+        let users = state.fetch_chat_users(1).await?;
+        let online: Vec<_> = users.iter().filter(|u| u.online).map(|u| u.id).collect();
+        assert_eq!(online, [2]);
 
         Ok(())
     }
@@ -1,9 +1,15 @@
 use crate::handlers::*;
 use crate::{
-    AppState, CreateChat, CreateMessage, CreateUser, ErrorOutput, ListMessages, SigninUser,
+    AppState, Bookmark, CreateAgent, CreateChat, CreateMessage, CreateUser, EditMessage,
+    ErrorOutput, FindSimilar, ListMessages, MarkRead, MessageEdit, MessagePage, PinnedMessage,
+    ScheduledMessage, ScheduledMessageStatus, SearchHit, SearchMessages, SigninUser, SimilarHit,
+    UpdateAgent, UpdateChat,
 };
 use axum::Router;
-use chat_core::{AgentType, Chat, ChatAgent, ChatType, ChatUser, Message, User, Workspace};
+use chat_core::{
+    AdapterType, AgentArgs, AgentType, Chat, ChatAgent, ChatRead, ChatType, ChatUser, Message,
+    ReactionCount, User, Workspace,
+};
 use utoipa::{
     openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
     Modify, OpenApi,
@@ -24,17 +30,38 @@ pub(crate) trait OpenApiRouter {
             list_chat_handler,
             create_chat_handler,
             get_chat_handler,
+            update_chat_handler,
+            delete_chat_handler,
+            mark_chat_read_handler,
             create_agent_handler,
+            get_agent_handler,
             update_agent_handler,
+            delete_agent_handler,
             list_agent_handler,
             list_message_handler,
+            edit_message_handler,
+            delete_message_handler,
+            list_thread_handler,
+            list_message_edits_handler,
+            add_reaction_handler,
+            remove_reaction_handler,
+            list_pins_handler,
+            pin_message_handler,
+            unpin_message_handler,
             send_message_handler,
+            list_scheduled_handler,
+            cancel_scheduled_handler,
+            search_messages_handler,
+            find_similar_handler,
+            list_bookmarks_handler,
+            add_bookmark_handler,
+            remove_bookmark_handler,
             list_chat_users_handler,
         ),
         components(
             schemas(
-                User, Chat, ChatType, ChatAgent, AgentType, ChatUser, Message, Workspace,
-                SigninUser, CreateUser, CreateChat, CreateMessage, ListMessages, AuthOutput, ErrorOutput
+                User, Chat, ChatRead, ChatType, ChatAgent, AgentType, AdapterType, AgentArgs, ChatUser, Message, ReactionCount, Workspace,
+                SigninUser, CreateUser, CreateChat, UpdateChat, MarkRead, CreateAgent, UpdateAgent, CreateMessage, EditMessage, MessageEdit, ListMessages, MessagePage, PinnedMessage, Bookmark, ScheduledMessage, ScheduledMessageStatus, SearchMessages, SearchHit, FindSimilar, SimilarHit, AuthOutput, ErrorOutput
             ),
         ),
         modifiers(&SecurityAddon),
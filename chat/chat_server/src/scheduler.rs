@@ -0,0 +1,46 @@
+use crate::{AppState, ScheduledMessage};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Deliver scheduled messages when they are due. Messages are claimed with
+/// `FOR UPDATE SKIP LOCKED`, so every chat_server instance could run a scheduler.
+pub fn spawn_message_scheduler(state: AppState) {
+    info!("Starting message scheduler");
+    tokio::spawn(async move { run_message_scheduler(state).await });
+}
+
+async fn run_message_scheduler(state: AppState) {
+    let poll_interval = Duration::from_millis(state.config.worker.poll_interval);
+    loop {
+        match state.claim_scheduled_message().await {
+            Ok(Some(scheduled)) => deliver(&state, &scheduled).await,
+            Ok(None) => sleep(poll_interval).await,
+            Err(e) => {
+                warn!("failed to claim scheduled message: {}", e);
+                sleep(poll_interval).await;
+            }
+        }
+    }
+}
+
+async fn deliver(state: &AppState, scheduled: &ScheduledMessage) {
+    match state.deliver_scheduled_message(scheduled).await {
+        Ok(message) => info!(
+            "scheduled message {} delivered as message {}",
+            scheduled.id, message.id
+        ),
+        Err(e) => {
+            warn!("scheduled message {} failed: {}", scheduled.id, e);
+            if let Err(e) = state
+                .fail_scheduled_message(scheduled.id as _, &e.to_string())
+                .await
+            {
+                warn!(
+                    "failed to mark scheduled message {} as failed: {}",
+                    scheduled.id, e
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{
+    agent::{message_content, AgentVariant},
+    models::AgentDecisionRecord,
+    AgentJob, AppError, AppState,
+};
+use chat_core::Agent;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::{info, warn};
+
+/// Run queued agent jobs (tap agents) in background. Each worker claims a job with
+/// `FOR UPDATE SKIP LOCKED`, so multiple workers (and multiple chat_server instances)
+/// can consume the same queue safely.
+pub fn spawn_agent_workers(state: AppState) {
+    let concurrency = state.config.worker.concurrency;
+    info!("Starting {} agent workers", concurrency);
+    for _ in 0..concurrency {
+        let state = state.clone();
+        tokio::spawn(async move { run_agent_worker(state).await });
+    }
+}
+
+async fn run_agent_worker(state: AppState) {
+    let poll_interval = Duration::from_millis(state.config.worker.poll_interval);
+    let job_timeout = Duration::from_secs(state.config.worker.timeout);
+    loop {
+        match state.claim_agent_job().await {
+            Ok(Some(job)) => {
+                // a hanging agent would otherwise hold the worker forever
+                let error = match timeout(job_timeout, state.run_agent_job(&job)).await {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "timed out".to_string(),
+                };
+                warn!("agent job {} failed: {}", job.id, error);
+                if let Err(e) = state.fail_agent_job(&job, &error).await {
+                    warn!("failed to reschedule agent job {}: {}", job.id, e);
+                }
+            }
+            Ok(None) => sleep(poll_interval).await,
+            Err(e) => {
+                warn!("failed to claim agent job: {}", e);
+                sleep(poll_interval).await;
+            }
+        }
+    }
+}
+
+impl AppState {
+    async fn run_agent_job(&self, job: &AgentJob) -> Result<(), AppError> {
+        let (Some(agent), Some(message)) = (
+            self.get_agent_by_id(job.agent_id as _).await?,
+            self.get_message_by_id(job.message_id as _).await?,
+        ) else {
+            // agent or message is gone, nothing to do
+            self.complete_agent_job(job.id as _).await?;
+            return Ok(());
+        };
+
+        let ctx = self
+            .build_agent_context(
+                message.chat_id as _,
+                message.sender_id as _,
+                Some(message.id as _),
+                message.thread_root_id,
+            )
+            .await?;
+        let content = message_content(&message);
+        let agent_id = agent.id;
+        let decision = AgentVariant::try_new(agent, &self.config)?
+            .process(content, &ctx)
+            .await?;
+        let record = AgentDecisionRecord::new(agent_id, decision);
+        self.record_agent_decisions(message.chat_id as _, Some(message.id), &[record])
+            .await?;
+        self.complete_agent_job(job.id as _).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ProviderConfig, CreateAgent, CreateMessage};
+    use anyhow::Result;
+    use chat_core::{AdapterType, AgentArgs, AgentType};
+    use mock_llm::MockLlm;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn tap_agent_job_should_record_output() -> Result<()> {
+        let server = MockLlm::new().respond("greeting").serve().await?;
+        let (_tdb, mut state) = AppState::new_for_test().await?;
+        let inner = Arc::get_mut(&mut state.inner).expect("state should not be shared yet");
+        inner.config.providers.insert(
+            "mock".to_string(),
+            ProviderConfig {
+                adapter: AdapterType::Openai,
+                base_url: Some(server.openai_url()),
+                api_key: Some("sk-test".to_string()),
+                api_key_env: None,
+                default_model: None,
+            },
+        );
+
+        // chat 2 has no other agents
+        let mut input = CreateAgent::new(
+            "classifier",
+            AgentType::Tap,
+            AdapterType::Openai,
+            "gpt-4o",
+            "Classify the message",
+            AgentArgs::default(),
+        );
+        input.provider = Some("mock".to_string());
+        let agent = state.create_agent(input, 2).await?;
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            files: vec![],
+            reply_to: None,
+            send_at: None,
+        };
+        let message = state.create_message(input, 2, 1).await?;
+
+        let job = state.claim_agent_job().await?.expect("job should exist");
+        state.run_agent_job(&job).await?;
+
+        let requests = server.llm.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].messages[0].content, "Classify the message");
+        assert_eq!(requests[0].messages.last().unwrap().content, "hello");
+
+        let (decision, content): (String, Option<String>) = sqlx::query_as(
+            "SELECT decision::text, content FROM agent_decisions WHERE message_id = $1 AND agent_id = $2",
+        )
+        .bind(message.id)
+        .bind(agent.id)
+        .fetch_one(&state.pool)
+        .await?;
+        assert_eq!(decision, "tap");
+        assert_eq!(content.as_deref(), Some("greeting"));
+
+        // the job is done
+        assert!(state.claim_agent_job().await?.is_none());
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chat_core::{Chat, ChatAgent, ChatType, Message};
 use futures::StreamExt;
 use reqwest::{
@@ -6,10 +6,14 @@ use reqwest::{
     StatusCode,
 };
 use reqwest_eventsource::{Event, EventSource};
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::json;
 use std::{net::SocketAddr, time::Duration};
-use tokio::{net::TcpListener, time::sleep};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    time::{sleep, timeout},
+};
 
 /*
 test1:
@@ -38,7 +42,10 @@ struct ChatServer {
     client: reqwest::Client,
 }
 
-struct NotifyServer;
+// events received by the notify server client, as (event, data)
+struct NotifyServer {
+    events: mpsc::UnboundedReceiver<(String, String)>,
+}
 
 const WILD_ADDR: &str = "0.0.0.0:0";
 
@@ -47,14 +54,39 @@ async fn chat_server_should_work() -> Result<()> {
     let (tdb, state) = chat_server::AppState::new_for_test().await?;
     let chat_server = ChatServer::new(state).await?;
     let db_url = tdb.url();
-    NotifyServer::new(&db_url, &chat_server.token).await?;
+    let mut notify_server = NotifyServer::new(&db_url, &chat_server.token).await?;
     let chat = chat_server.create_chat().await?;
-    let _agent = chat_server.create_agent(chat.id as u64).await?;
+    let agent = chat_server.create_agent(chat.id as u64).await?;
     let _msg = chat_server.create_message(chat.id as u64).await?;
     sleep(Duration::from_secs(1)).await;
+
+    let events = notify_server.received();
+    let chat: Chat = find_event(&events, "NewChat")?;
+    assert_eq!(chat.name.as_ref().unwrap(), "test");
+    assert_eq!(chat.members, vec![1, 2]);
+    assert_eq!(chat.r#type, ChatType::PrivateChannel);
+
+    // chat is updated when the agent is added
+    let chat: Chat = find_event(&events, "AddToChat")?;
+    assert_eq!(chat.name.as_ref().unwrap(), "test");
+    assert_eq!(chat.agents, vec![agent.id]);
+
+    let msg: Message = find_event(&events, "NewMessage")?;
+    assert_eq!(msg.content, "hello");
+    assert_eq!(msg.files.len(), 1);
+    assert_eq!(msg.sender_id, 1);
     Ok(())
 }
 
+// the data of the first event with the name
+fn find_event<T: DeserializeOwned>(events: &[(String, String)], name: &str) -> Result<T> {
+    let (_, data) = events
+        .iter()
+        .find(|(event, _)| event == name)
+        .with_context(|| format!("{name} should be received"))?;
+    Ok(serde_json::from_str(data)?)
+}
+
 impl NotifyServer {
     async fn new(db_url: &str, token: &str) -> Result<Self> {
         let mut config = notify_server::AppConfig::load()?;
@@ -70,29 +102,25 @@ impl NotifyServer {
         });
 
         let mut es = EventSource::get(format!("http://{}/events?token={}", addr, token));
+        let (tx, events) = mpsc::unbounded_channel();
+        let (open_tx, open_rx) = oneshot::channel();
+        let mut open_tx = Some(open_tx);
 
+        // events are checked by the test, a panic here would go unnoticed
         tokio::spawn(async move {
             while let Some(event) = es.next().await {
                 match event {
-                    Ok(Event::Open) => println!("Connection Open!"),
-                    Ok(Event::Message(message)) => match message.event.as_str() {
-                        "NewChat" => {
-                            let chat: Chat = serde_json::from_str(&message.data).unwrap();
-                            assert_eq!(chat.name.as_ref().unwrap(), "test");
-                            assert_eq!(chat.members, vec![1, 2]);
-                            assert_eq!(chat.r#type, ChatType::PrivateChannel);
+                    Ok(Event::Open) => {
+                        println!("Connection Open!");
+                        if let Some(open_tx) = open_tx.take() {
+                            let _ = open_tx.send(());
                         }
-
-                        "NewMessage" => {
-                            let msg: Message = serde_json::from_str(&message.data).unwrap();
-                            assert_eq!(msg.content, "hello");
-                            assert_eq!(msg.files.len(), 1);
-                            assert_eq!(msg.sender_id, 1);
-                        }
-                        _ => {
-                            panic!("unexpected event: {:?}", message);
+                    }
+                    Ok(Event::Message(message)) => {
+                        if tx.send((message.event, message.data)).is_err() {
+                            break;
                         }
-                    },
+                    }
                     Err(err) => {
                         println!("Error: {}", err);
                         es.close();
@@ -101,7 +129,17 @@ impl NotifyServer {
             }
         });
 
-        Ok(Self)
+        // events sent before the stream is open are not received
+        timeout(Duration::from_secs(5), open_rx).await??;
+        Ok(Self { events })
+    }
+
+    fn received(&mut self) -> Vec<(String, String)> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            events.push(event);
+        }
+        events
     }
 }
 
@@ -0,0 +1,12 @@
+-- track who created the chat, only creator or workspace owner could delete it
+ALTER TABLE chats
+    ADD COLUMN created_by bigint REFERENCES users(id);
+
+-- when a chat is deleted, its messages and agents should go with it
+ALTER TABLE messages
+    DROP CONSTRAINT messages_chat_id_fkey,
+    ADD CONSTRAINT messages_chat_id_fkey FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE;
+
+ALTER TABLE chat_agents
+    DROP CONSTRAINT chat_agents_chat_id_fkey,
+    ADD CONSTRAINT chat_agents_chat_id_fkey FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE;
@@ -0,0 +1,27 @@
+-- if message updated (e.g. agent reply being streamed), notify with message data
+CREATE OR REPLACE FUNCTION update_message()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    RAISE NOTICE 'update_message: %', NEW;
+    SELECT
+      members INTO USERS
+    FROM
+      chats
+    WHERE
+      id = NEW.chat_id;
+    PERFORM
+      pg_notify('chat_message_updated', json_build_object('message', NEW, 'members', USERS)::text);
+  END IF;
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER update_message_trigger
+  AFTER UPDATE ON messages
+  FOR EACH ROW
+  EXECUTE FUNCTION update_message();
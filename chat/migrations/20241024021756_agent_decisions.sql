@@ -0,0 +1,22 @@
+-- add agent_decision_type type
+CREATE TYPE agent_decision_type AS ENUM(
+    'modify',
+    'reply',
+    'delete',
+    'none'
+);
+
+-- record what every agent decided for a message. message_id is null if the message was deleted by an agent
+CREATE TABLE IF NOT EXISTS agent_decisions(
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    message_id bigint REFERENCES messages(id) ON DELETE CASCADE,
+    agent_id bigint NOT NULL REFERENCES chat_agents(id) ON DELETE CASCADE,
+    decision agent_decision_type NOT NULL,
+    content text,
+    -- the message created by a reply agent
+    reply_id bigint REFERENCES messages(id) ON DELETE SET NULL,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS agent_decisions_message_id_index ON agent_decisions(message_id);
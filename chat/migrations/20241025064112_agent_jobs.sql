@@ -0,0 +1,26 @@
+-- add agent_job_status type
+CREATE TYPE agent_job_status AS ENUM(
+    'pending',
+    'running',
+    'done',
+    'dead'
+);
+
+-- durable queue for agents running in background (tap agents)
+CREATE TABLE IF NOT EXISTS agent_jobs(
+    id bigserial PRIMARY KEY,
+    agent_id bigint NOT NULL REFERENCES chat_agents(id) ON DELETE CASCADE,
+    message_id bigint NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    status agent_job_status NOT NULL DEFAULT 'pending',
+    attempts int NOT NULL DEFAULT 0,
+    last_error text,
+    -- job won't be picked up before run_at, used for retry backoff
+    run_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- workers only look for pending or running jobs
+CREATE INDEX IF NOT EXISTS agent_jobs_status_run_at_index ON agent_jobs(status, run_at)
+WHERE
+    status IN ('pending', 'running');
@@ -0,0 +1,29 @@
+-- agent names are unique per chat, not globally
+ALTER TABLE chat_agents
+    DROP CONSTRAINT chat_agents_name_key,
+    ADD CONSTRAINT chat_agents_chat_id_name_key UNIQUE (chat_id, name);
+
+-- keep chats.agents in sync with chat_agents
+CREATE OR REPLACE FUNCTION sync_chat_agents()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    UPDATE chats SET agents = array_append(agents, NEW.id) WHERE id = NEW.chat_id;
+  ELSIF TG_OP = 'DELETE' THEN
+    UPDATE chats SET agents = array_remove(agents, OLD.id) WHERE id = OLD.chat_id;
+  END IF;
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER sync_chat_agents_trigger
+  AFTER INSERT OR DELETE ON chat_agents
+  FOR EACH ROW
+  EXECUTE FUNCTION sync_chat_agents();
+
+UPDATE chats SET agents = COALESCE(
+    (SELECT array_agg(id ORDER BY id) FROM chat_agents WHERE chat_id = chats.id),
+    '{}'
+);
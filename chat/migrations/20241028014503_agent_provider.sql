@@ -0,0 +1,2 @@
+-- agents could use a named provider configured in chat_server, e.g. a self-hosted gateway
+ALTER TABLE chat_agents ADD COLUMN provider TEXT;
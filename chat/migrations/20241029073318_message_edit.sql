@@ -0,0 +1,44 @@
+-- messages could be edited and (soft) deleted by their authors
+ALTER TABLE messages
+    ADD COLUMN edited_at timestamptz,
+    ADD COLUMN deleted_at timestamptz;
+
+-- previous versions of edited messages, created_at is when the version was replaced
+CREATE TABLE IF NOT EXISTS message_edits(
+  id bigserial PRIMARY KEY,
+  message_id bigint NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  content text NOT NULL,
+  modified_content text,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS message_edits_message_id_idx ON message_edits(message_id, id);
+
+-- if message updated, notify with message data. Soft deleted messages are notified
+-- on a separate channel.
+CREATE OR REPLACE FUNCTION update_message()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    RAISE NOTICE 'update_message: %', NEW;
+    SELECT
+      members INTO USERS
+    FROM
+      chats
+    WHERE
+      id = NEW.chat_id;
+    IF NEW.deleted_at IS NOT NULL AND OLD.deleted_at IS NULL THEN
+      PERFORM
+        pg_notify('chat_message_deleted', json_build_object('message', NEW, 'members', USERS)::text);
+    ELSIF NEW.deleted_at IS NULL THEN
+      PERFORM
+        pg_notify('chat_message_updated', json_build_object('message', NEW, 'members', USERS)::text);
+    END IF;
+  END IF;
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,9 @@
+-- threaded replies: reply_to is the message being replied, thread_root_id is the top
+-- level message the thread hangs on. reply_count is only maintained for root messages.
+ALTER TABLE messages
+    ADD COLUMN reply_to bigint REFERENCES messages(id) ON DELETE SET NULL,
+    ADD COLUMN thread_root_id bigint REFERENCES messages(id) ON DELETE SET NULL,
+    ADD COLUMN reply_count integer NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS messages_thread_root_id_idx ON messages(thread_root_id, id)
+    WHERE thread_root_id IS NOT NULL;
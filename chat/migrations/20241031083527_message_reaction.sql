@@ -0,0 +1,45 @@
+-- emoji reactions on messages, a user could react with an emoji once on a message
+CREATE TABLE IF NOT EXISTS message_reactions(
+  message_id bigint NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  emoji varchar(32) NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (message_id, user_id, emoji)
+);
+
+-- if a reaction is added or removed, notify chat members
+CREATE OR REPLACE FUNCTION change_reaction()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  REACTION message_reactions;
+  CHAT_ID bigint;
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    REACTION := NEW;
+  ELSE
+    REACTION := OLD;
+  END IF;
+  RAISE NOTICE 'change_reaction: %', REACTION;
+  SELECT
+    c.id, c.members INTO CHAT_ID, USERS
+  FROM
+    messages m
+    JOIN chats c ON c.id = m.chat_id
+  WHERE
+    m.id = REACTION.message_id;
+  -- the message is being deleted, no need to notify
+  IF CHAT_ID IS NOT NULL THEN
+    PERFORM
+      pg_notify('chat_message_reacted', json_build_object('reaction', json_build_object('chat_id', CHAT_ID, 'message_id', REACTION.message_id, 'user_id', REACTION.user_id, 'emoji', REACTION.emoji, 'added', TG_OP = 'INSERT'), 'members', USERS)::text);
+  END IF;
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER change_reaction_trigger
+  AFTER INSERT OR DELETE ON message_reactions
+  FOR EACH ROW
+  EXECUTE FUNCTION change_reaction();
@@ -0,0 +1,26 @@
+-- read position of a user in a chat, messages after it are unread
+CREATE TABLE IF NOT EXISTS chat_reads(
+  chat_id bigint NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  last_read_message_id bigint NOT NULL,
+  updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (chat_id, user_id)
+);
+
+-- if the read position moved, notify the user so that their other devices are synced
+CREATE OR REPLACE FUNCTION update_chat_read()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  RAISE NOTICE 'update_chat_read: %', NEW;
+  PERFORM
+    pg_notify('chat_read_updated', row_to_json(NEW)::text);
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER update_chat_read_trigger
+  AFTER INSERT OR UPDATE ON chat_reads
+  FOR EACH ROW
+  EXECUTE FUNCTION update_chat_read();
@@ -0,0 +1,5 @@
+-- full text search on messages, content modified by agents (e.g. translations) is
+-- searchable too. `simple` config is used since messages could be in any language.
+-- It's an expression index rather than a column so that the message notifications
+-- (which carry the whole row) don't grow. Queries must use the same expression.
+CREATE INDEX IF NOT EXISTS messages_content_search_idx ON messages USING GIN (to_tsvector('simple', content || ' ' || COALESCE(modified_content, '')));
@@ -0,0 +1,41 @@
+-- users and agents mentioned as `@name` in a message, parsed when the message is
+-- created or edited
+ALTER TABLE messages
+  ADD COLUMN mentions bigint[] NOT NULL DEFAULT '{}',
+  ADD COLUMN agent_mentions bigint[] NOT NULL DEFAULT '{}';
+
+-- find messages mentioning a user
+CREATE INDEX IF NOT EXISTS messages_mentions_idx ON messages USING GIN(mentions);
+
+-- if users are mentioned in a new or edited message, notify them. On edit only the
+-- newly mentioned ones are notified.
+CREATE OR REPLACE FUNCTION mention_users()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    USERS := NEW.mentions;
+  ELSE
+    USERS := ARRAY (
+      SELECT
+        unnest(NEW.mentions)
+      EXCEPT
+      SELECT
+        unnest(OLD.mentions));
+  END IF;
+  IF cardinality(USERS) > 0 THEN
+    RAISE NOTICE 'mention_users: %', USERS;
+    PERFORM
+      pg_notify('chat_message_mentioned', json_build_object('mention', json_build_object('chat_id', NEW.chat_id, 'message_id', NEW.id, 'sender_id', NEW.sender_id, 'thread_root_id', NEW.thread_root_id), 'users', USERS)::text);
+  END IF;
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER mention_users_trigger
+  AFTER INSERT OR UPDATE OF mentions ON messages
+  FOR EACH ROW
+  EXECUTE FUNCTION mention_users();
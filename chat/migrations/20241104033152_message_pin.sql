@@ -0,0 +1,52 @@
+-- messages pinned in a chat, e.g. runbooks or decisions
+CREATE TABLE IF NOT EXISTS message_pins(
+  message_id bigint PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+  chat_id bigint NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  pinned_by bigint NOT NULL REFERENCES users(id),
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS message_pins_chat_id_idx ON message_pins(chat_id, created_at DESC);
+
+-- private bookmarks of a user, across all the chats
+CREATE TABLE IF NOT EXISTS message_bookmarks(
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  message_id bigint NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, message_id)
+);
+
+-- if a message is pinned or unpinned, notify chat members
+CREATE OR REPLACE FUNCTION change_pin()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  PIN message_pins;
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PIN := NEW;
+  ELSE
+    PIN := OLD;
+  END IF;
+  RAISE NOTICE 'change_pin: %', PIN;
+  SELECT
+    members INTO USERS
+  FROM
+    chats
+  WHERE
+    id = PIN.chat_id;
+  -- the chat is being deleted, no need to notify
+  IF USERS IS NOT NULL THEN
+    PERFORM
+      pg_notify('chat_message_pinned', json_build_object('pin', json_build_object('chat_id', PIN.chat_id, 'message_id', PIN.message_id, 'user_id', PIN.pinned_by, 'pinned', TG_OP = 'INSERT'), 'members', USERS)::text);
+  END IF;
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER change_pin_trigger
+  AFTER INSERT OR DELETE ON message_pins
+  FOR EACH ROW
+  EXECUTE FUNCTION change_pin();
@@ -0,0 +1,32 @@
+-- add scheduled_message_status type
+CREATE TYPE scheduled_message_status AS ENUM(
+    'pending',
+    'sent',
+    'cancelled',
+    'failed'
+);
+
+-- messages to be sent later. They are not in `messages` until delivered, so they don't
+-- show up in the chat nor notify anyone before that.
+CREATE TABLE IF NOT EXISTS scheduled_messages(
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    sender_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content text NOT NULL,
+    files text[] NOT NULL DEFAULT '{}',
+    reply_to bigint REFERENCES messages(id) ON DELETE SET NULL,
+    send_at timestamptz NOT NULL,
+    status scheduled_message_status NOT NULL DEFAULT 'pending',
+    -- the delivered message
+    message_id bigint REFERENCES messages(id) ON DELETE SET NULL,
+    last_error text,
+    created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the scheduler only looks for pending messages which are due
+CREATE INDEX IF NOT EXISTS scheduled_messages_send_at_index ON scheduled_messages(send_at)
+WHERE
+    status = 'pending';
+
+CREATE INDEX IF NOT EXISTS scheduled_messages_chat_id_sender_id_index ON scheduled_messages(chat_id, sender_id);
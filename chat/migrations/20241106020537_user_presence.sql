@@ -0,0 +1,9 @@
+-- presence reported by clients to notify_server. notify_server keeps it in memory and
+-- mirrors it here so chat_server could tell who is online. A row is stale after
+-- expires_at, e.g. notify_server went away before marking the user offline.
+CREATE TABLE IF NOT EXISTS user_presence(
+  user_id bigint PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  status varchar(16) NOT NULL,
+  expires_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
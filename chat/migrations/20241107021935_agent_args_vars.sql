@@ -0,0 +1,14 @@
+-- agent args used to be free form, keys other than the typed ones are template variables
+-- now and kept under `vars`
+UPDATE chat_agents a
+SET args = (a.args - ARRAY(SELECT jsonb_object_keys(v.extra)))
+  || jsonb_build_object('vars', COALESCE(a.args->'vars', '{}'::jsonb) || v.extra)
+FROM (
+  SELECT id, args - ARRAY[
+    'vars', 'historySize', 'history_size', 'replyInThread', 'reply_in_thread',
+    'onMention', 'on_mention'
+  ] AS extra
+  FROM chat_agents
+  WHERE jsonb_typeof(args) = 'object'
+) v
+WHERE a.id = v.id AND v.extra != '{}'::jsonb;
@@ -0,0 +1,33 @@
+-- report who unpinned a message instead of who pinned it. chat_server sets `chat.actor_id`
+-- in the transaction deleting the pin
+CREATE OR REPLACE FUNCTION change_pin()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  PIN message_pins;
+  USERS bigint[];
+  ACTOR bigint;
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PIN := NEW;
+    ACTOR := NEW.pinned_by;
+  ELSE
+    PIN := OLD;
+    ACTOR := COALESCE(NULLIF(current_setting('chat.actor_id', TRUE), '')::bigint, OLD.pinned_by);
+  END IF;
+  RAISE NOTICE 'change_pin: %', PIN;
+  SELECT
+    members INTO USERS
+  FROM
+    chats
+  WHERE
+    id = PIN.chat_id;
+  -- the chat is being deleted, no need to notify
+  IF USERS IS NOT NULL THEN
+    PERFORM
+      pg_notify('chat_message_pinned', json_build_object('pin', json_build_object('chat_id', PIN.chat_id, 'message_id', PIN.message_id, 'user_id', ACTOR, 'pinned', TG_OP = 'INSERT'), 'members', USERS)::text);
+  END IF;
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,27 @@
+-- deleted messages are notified with their ids only, the content is gone for the members
+CREATE OR REPLACE FUNCTION update_message()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    RAISE NOTICE 'update_message: %', NEW;
+    SELECT
+      members INTO USERS
+    FROM
+      chats
+    WHERE
+      id = NEW.chat_id;
+    IF NEW.deleted_at IS NOT NULL AND OLD.deleted_at IS NULL THEN
+      PERFORM
+        pg_notify('chat_message_deleted', json_build_object('message', json_build_object('id', NEW.id, 'chat_id', NEW.chat_id, 'thread_root_id', NEW.thread_root_id), 'members', USERS)::text);
+    ELSIF NEW.deleted_at IS NULL THEN
+      PERFORM
+        pg_notify('chat_message_updated', json_build_object('message', NEW, 'members', USERS)::text);
+    END IF;
+  END IF;
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
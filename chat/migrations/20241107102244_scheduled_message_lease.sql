@@ -0,0 +1,7 @@
+-- scheduled messages are claimed into `sending` with a lease, and marked as `sent` along
+-- with the delivered message. A message whose lease expired (e.g. the instance crashed)
+-- is claimed again.
+ALTER TYPE scheduled_message_status ADD VALUE IF NOT EXISTS 'sending' AFTER 'pending';
+
+ALTER TABLE scheduled_messages
+    ADD COLUMN locked_until timestamptz;
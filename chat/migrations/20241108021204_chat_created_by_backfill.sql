@@ -0,0 +1,13 @@
+-- chats created before `created_by` was tracked: the first member who sent a message is
+-- the most likely creator, otherwise the first member
+UPDATE chats c
+SET created_by = COALESCE(
+    (
+      SELECT m.sender_id
+      FROM messages m
+      WHERE m.chat_id = c.id AND m.sender_id = ANY(c.members)
+      ORDER BY m.id
+      LIMIT 1
+    ),
+    c.members[1])
+WHERE c.created_by IS NULL;
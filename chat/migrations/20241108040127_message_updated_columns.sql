@@ -0,0 +1,36 @@
+-- only edits members see are sent as the full message: streamed agent replies and edits
+-- change the content, deletes set deleted_at. Reply counts of thread roots get their own
+-- small event.
+DROP TRIGGER IF EXISTS update_message_trigger ON messages;
+
+CREATE TRIGGER update_message_trigger
+  AFTER UPDATE OF content, modified_content, files, deleted_at ON messages
+  FOR EACH ROW
+  WHEN ((OLD.content, OLD.modified_content, OLD.files, OLD.deleted_at) IS DISTINCT FROM (NEW.content, NEW.modified_content, NEW.files, NEW.deleted_at))
+  EXECUTE FUNCTION update_message();
+
+CREATE OR REPLACE FUNCTION update_reply_count()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  RAISE NOTICE 'update_reply_count: %', NEW.id;
+  SELECT
+    members INTO USERS
+  FROM
+    chats
+  WHERE
+    id = NEW.chat_id;
+  PERFORM
+    pg_notify('chat_reply_count_updated', json_build_object('thread', json_build_object('chat_id', NEW.chat_id, 'message_id', NEW.id, 'reply_count', NEW.reply_count), 'members', USERS)::text);
+  RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER update_reply_count_trigger
+  AFTER UPDATE OF reply_count ON messages
+  FOR EACH ROW
+  WHEN (OLD.reply_count IS DISTINCT FROM NEW.reply_count)
+  EXECUTE FUNCTION update_reply_count();
@@ -0,0 +1,21 @@
+-- every agent replies as its own bot user, created along with the agent. The email is
+-- under the reserved .invalid domain so it can never belong to a real user
+ALTER TABLE chat_agents
+    ADD COLUMN bot_user_id bigint REFERENCES users(id);
+
+-- bot users created on the first reply of an agent are taken over
+UPDATE users u
+SET email = 'agent-' || a.id || '@agents.invalid'
+FROM chat_agents a
+WHERE u.email = 'agent-' || a.id || '@bot.org' AND u.is_bot AND u.password_hash = '';
+
+INSERT INTO users (ws_id, fullname, email, password_hash, is_bot)
+SELECT c.ws_id, left(a.name, 64), 'agent-' || a.id || '@agents.invalid', '', TRUE
+FROM chat_agents a
+JOIN chats c ON c.id = a.chat_id
+ON CONFLICT (email) DO NOTHING;
+
+UPDATE chat_agents a
+SET bot_user_id = u.id
+FROM users u
+WHERE u.email = 'agent-' || a.id || '@agents.invalid';
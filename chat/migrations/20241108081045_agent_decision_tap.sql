@@ -0,0 +1,2 @@
+-- the output of tap agents is kept in agent_decisions.content
+ALTER TYPE agent_decision_type ADD VALUE IF NOT EXISTS 'tap';
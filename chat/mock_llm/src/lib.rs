@@ -0,0 +1,216 @@
+mod ollama;
+mod openai;
+
+use axum::{routing::post, Router};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::net::TcpListener;
+
+/// An LLM server speaking OpenAI's `/v1/chat/completions`, `/v1/embeddings` and
+/// ollama's `/api/chat`, so adapters and agents could be tested without network.
+///
+/// A reply is picked in order from: the first rule whose pattern is in the last
+/// message, the queued responses, and finally the last message echoed back.
+#[derive(Debug, Clone)]
+pub struct MockLlm {
+    inner: Arc<Mutex<MockLlmInner>>,
+}
+
+#[derive(Debug)]
+struct MockLlmInner {
+    rules: Vec<(String, String)>,
+    responses: VecDeque<String>,
+    requests: Vec<ChatRequest>,
+    dimensions: usize,
+}
+
+/// A running mock server, it stops when the runtime is dropped.
+#[derive(Debug, Clone)]
+pub struct MockLlmServer {
+    pub addr: SocketAddr,
+    pub llm: MockLlm,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// A chat completion request received by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub stream: Option<bool>,
+}
+
+impl MockLlm {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MockLlmInner {
+                rules: Vec::new(),
+                responses: VecDeque::new(),
+                requests: Vec::new(),
+                dimensions: 1536,
+            })),
+        }
+    }
+
+    /// Queue a canned reply, queued replies are used once in order
+    pub fn respond(self, reply: impl Into<String>) -> Self {
+        self.lock().responses.push_back(reply.into());
+        self
+    }
+
+    /// Always reply with `reply` when the last message contains `pattern`
+    pub fn when(self, pattern: impl Into<String>, reply: impl Into<String>) -> Self {
+        self.lock().rules.push((pattern.into(), reply.into()));
+        self
+    }
+
+    /// Dimensions of the generated embeddings, default to 1536 (text-embedding-3-small)
+    pub fn dimensions(self, dimensions: usize) -> Self {
+        self.lock().dimensions = dimensions;
+        self
+    }
+
+    /// All chat requests received so far
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.lock().requests.clone()
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/v1/chat/completions", post(openai::chat_handler))
+            .route("/v1/embeddings", post(openai::embeddings_handler))
+            .route("/api/chat", post(ollama::chat_handler))
+            .with_state(self.clone())
+    }
+
+    /// Serve on a random local port
+    pub async fn serve(self) -> anyhow::Result<MockLlmServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = self.router();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok(MockLlmServer { addr, llm: self })
+    }
+
+    pub(crate) fn reply(&self, req: ChatRequest) -> String {
+        let mut inner = self.lock();
+        let last = req
+            .messages
+            .last()
+            .map(|m| m.content.clone())
+            .unwrap_or_default();
+        inner.requests.push(req);
+
+        if let Some((_, reply)) = inner.rules.iter().find(|(p, _)| last.contains(p.as_str())) {
+            return reply.clone();
+        }
+        inner.responses.pop_front().unwrap_or(last)
+    }
+
+    pub(crate) fn embed(&self, input: &str) -> Vec<f32> {
+        embed(input, self.lock().dimensions)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockLlmInner> {
+        self.inner.lock().expect("mock llm lock poisoned")
+    }
+}
+
+impl Default for MockLlm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockLlmServer {
+    /// Base url for ollama adapter
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Base url for OpenAI adapter
+    pub fn openai_url(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+}
+
+/// Split a reply into tokens for streaming, concatenating them gives the reply back.
+pub(crate) fn tokenize(reply: &str) -> Vec<String> {
+    reply.split_inclusive(' ').map(|s| s.to_string()).collect()
+}
+
+// a deterministic bag of words embedding: texts sharing words are close to each other
+fn embed(input: &str, dimensions: usize) -> Vec<f32> {
+    let dimensions = dimensions.max(1);
+    let mut v = vec![0f32; dimensions];
+    for word in input.split_whitespace() {
+        let word = word.to_lowercase();
+        // FNV-1a, stable across runs and rust versions
+        let hash = word.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        v[(hash % dimensions as u64) as usize] += 1.0;
+    }
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content: &str) -> ChatRequest {
+        ChatRequest {
+            model: "gpt-4o".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: content.to_string(),
+            }],
+            stream: None,
+        }
+    }
+
+    #[test]
+    fn reply_should_follow_rules_then_responses_then_echo() {
+        let llm = MockLlm::new()
+            .when("weather", "It's sunny")
+            .respond("first")
+            .respond("second");
+        assert_eq!(llm.reply(request("hi")), "first");
+        assert_eq!(llm.reply(request("how is the weather?")), "It's sunny");
+        assert_eq!(llm.reply(request("hi")), "second");
+        assert_eq!(llm.reply(request("hi")), "hi");
+        assert_eq!(llm.requests().len(), 4);
+    }
+
+    #[test]
+    fn tokenize_should_keep_content() {
+        let tokens = tokenize("hello big world");
+        assert_eq!(tokens, ["hello ", "big ", "world"]);
+        assert_eq!(tokens.concat(), "hello big world");
+    }
+
+    #[test]
+    fn embed_should_be_deterministic() {
+        let a = embed("the quick brown fox", 64);
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, embed("The quick brown fox", 64));
+
+        let dot = |x: &[f32], y: &[f32]| x.iter().zip(y).map(|(a, b)| a * b).sum::<f32>();
+        let close = embed("the quick brown dog", 64);
+        let far = embed("lorem ipsum", 64);
+        assert!(dot(&a, &close) > dot(&a, &far));
+    }
+}
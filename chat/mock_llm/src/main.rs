@@ -0,0 +1,24 @@
+use anyhow::Result;
+use mock_llm::MockLlm;
+use std::env;
+use tokio::net::TcpListener;
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
+
+// a standalone mock server which echoes the last message back, e.g. to run the
+// chat server locally without an LLM. Point a provider's base_url to it.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let layer = Layer::new().with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry().with(layer).init();
+
+    let addr = env::var("MOCK_LLM_ADDR").unwrap_or_else(|_| "0.0.0.0:11435".to_string());
+    let app = MockLlm::new().router();
+
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Listening on: {}", addr);
+
+    axum::serve(listener, app.into_make_service()).await?;
+
+    Ok(())
+}
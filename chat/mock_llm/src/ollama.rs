@@ -0,0 +1,59 @@
+use crate::{tokenize, ChatRequest, MockLlm};
+use axum::{
+    body::Body,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::stream;
+use serde_json::json;
+use std::convert::Infallible;
+
+pub(crate) async fn chat_handler(
+    State(llm): State<MockLlm>,
+    Json(req): Json<ChatRequest>,
+) -> Response {
+    let model = req.model.clone();
+    // unlike OpenAI, ollama streams by default
+    let is_stream = req.stream.unwrap_or(true);
+    let reply = llm.reply(req);
+    let created_at = chrono::Utc::now().to_rfc3339();
+
+    let done = |content: &str| {
+        json!({
+            "model": model,
+            "created_at": created_at,
+            "message": { "role": "assistant", "content": content },
+            "done": true,
+            "total_duration": 0,
+            "load_duration": 0,
+            "prompt_eval_count": 0,
+            "prompt_eval_duration": 0,
+            "eval_count": 0,
+            "eval_duration": 0
+        })
+    };
+
+    if !is_stream {
+        return Json(done(&reply)).into_response();
+    }
+
+    // newline delimited json, the last chunk has `done: true`
+    let mut lines: Vec<_> = tokenize(&reply)
+        .into_iter()
+        .map(|token| {
+            let chunk = json!({
+                "model": model,
+                "created_at": created_at,
+                "message": { "role": "assistant", "content": token },
+                "done": false
+            });
+            format!("{chunk}\n")
+        })
+        .collect();
+    lines.push(format!("{}\n", done("")));
+
+    let body = Body::from_stream(stream::iter(lines.into_iter().map(Ok::<_, Infallible>)));
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
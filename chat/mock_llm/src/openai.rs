@@ -0,0 +1,102 @@
+use crate::{tokenize, ChatRequest, MockLlm};
+use axum::{
+    body::Body,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::stream;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingRequest {
+    model: String,
+    input: EmbeddingInput,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+pub(crate) async fn chat_handler(
+    State(llm): State<MockLlm>,
+    Json(req): Json<ChatRequest>,
+) -> Response {
+    let model = req.model.clone();
+    let is_stream = req.stream.unwrap_or(false);
+    let reply = llm.reply(req);
+    let created = chrono::Utc::now().timestamp();
+
+    if !is_stream {
+        let tokens = tokenize(&reply).len();
+        return Json(json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "created": created,
+            "model": model,
+            "system_fingerprint": "fp_mock",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": reply },
+                "logprobs": null,
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 0,
+                "completion_tokens": tokens,
+                "total_tokens": tokens
+            }
+        }))
+        .into_response();
+    }
+
+    // server sent events, one chunk per token, terminated by `data: [DONE]`
+    let chunk = |delta: Value, finish_reason: Option<&str>| {
+        let data = json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+        });
+        format!("data: {data}\n\n")
+    };
+    let mut events = vec![chunk(json!({ "role": "assistant", "content": "" }), None)];
+    events.extend(
+        tokenize(&reply)
+            .into_iter()
+            .map(|token| chunk(json!({ "content": token }), None)),
+    );
+    events.push(chunk(json!({}), Some("stop")));
+    events.push("data: [DONE]\n\n".to_string());
+
+    let body = Body::from_stream(stream::iter(events.into_iter().map(Ok::<_, Infallible>)));
+    ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+}
+
+pub(crate) async fn embeddings_handler(
+    State(llm): State<MockLlm>,
+    Json(req): Json<EmbeddingRequest>,
+) -> impl IntoResponse {
+    let input = match req.input {
+        EmbeddingInput::One(s) => vec![s],
+        EmbeddingInput::Many(v) => v,
+    };
+    let data: Vec<_> = input
+        .iter()
+        .enumerate()
+        .map(|(index, s)| json!({ "object": "embedding", "index": index, "embedding": llm.embed(s) }))
+        .collect();
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "model": req.model,
+        "usage": { "prompt_tokens": 0, "total_tokens": 0 }
+    }))
+}
@@ -16,6 +16,18 @@ pub enum AppError {
 
     #[error("jwt error: {0}")]
     JwtError(#[from] jwt_simple::Error),
+
+    #[error("sql error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error("user {user_id} is not member of chat {chat_id}")]
+    NotChatMemberError { user_id: u64, chat_id: u64 },
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("invalid frame: {0}")]
+    InvalidFrameError(#[from] serde_json::Error),
 }
 
 impl ErrorOutput {
@@ -31,6 +43,10 @@ impl IntoResponse for AppError {
         let status = match &self {
             Self::JwtError(_) => StatusCode::FORBIDDEN,
             Self::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::SqlxError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotChatMemberError { .. } => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidFrameError(_) => StatusCode::BAD_REQUEST,
         };
 
         (status, Json(ErrorOutput::new(self.to_string()))).into_response()
@@ -0,0 +1,48 @@
+use crate::AppState;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Status of the Postgres listener all events come from
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerHealth {
+    pub connected: bool,
+    /// when the listener got connected, or lost the connection
+    pub since: Option<DateTime<Utc>>,
+    /// why the connection was lost, or the last reconnect failed
+    pub last_error: Option<String>,
+    pub reconnects: u64,
+    pub last_notification_at: Option<DateTime<Utc>>,
+    /// notifications which couldn't be loaded and were skipped
+    pub failed_notifications: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub listener: ListenerHealth,
+}
+
+/// 503 while the listener is disconnected, as no events are delivered meanwhile.
+pub(crate) async fn health_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let listener = state.listener_health();
+    let status = if listener.connected {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Health { listener }))
+}
+
+impl AppState {
+    pub fn listener_health(&self) -> ListenerHealth {
+        self.listener
+            .lock()
+            .expect("listener health poisoned")
+            .clone()
+    }
+
+    pub(crate) fn update_listener_health(&self, f: impl FnOnce(&mut ListenerHealth)) {
+        f(&mut self.listener.lock().expect("listener health poisoned"));
+    }
+}
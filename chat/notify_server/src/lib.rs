@@ -1,13 +1,36 @@
+//! Pushes chat events to connected users over SSE (`/events`) and WebSocket (`/ws`).
+//!
+//! # Fan-out
+//!
+//! Postgres is the bus between instances. chat_server's triggers `pg_notify` events with the
+//! users they concern, and every instance LISTENs on all channels. Each instance only sends
+//! them to the users connected to it, so any number of replicas can run behind a load
+//! balancer without knowing about each other. Events originating here, like typing and
+//! presence, are published through the `chat_user_event` channel the same way, and presence
+//! is mirrored to `user_presence` so replicas agree on who is online.
+//!
+//! Users connected to an instance have a channel shared by their connections, dropped with
+//! the last one, so memory is bounded by the connected users. It keeps the recent events for
+//! connections to resume with `Last-Event-ID` (`{instance id}-{seq}`). A connection resuming
+//! from an id the instance can't replay from, e.g. sent by another instance, gets a `Resync`
+//! event to reload its state. `/metrics` reports the connections and memory held by the
+//! instance, `/health` whether its listener is connected.
+
 mod config;
 mod error;
+mod health;
+mod metrics;
 mod notif;
+mod presence;
+mod replay;
 mod sse;
+mod ws;
 
 use axum::{
     http::Method,
     middleware::from_fn_with_state,
     response::{Html, IntoResponse},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use chat_core::{
@@ -15,16 +38,30 @@ use chat_core::{
     DecodingKey, User,
 };
 use dashmap::DashMap;
+use health::health_handler;
+use metrics::metrics_handler;
+use presence::{presence_handler, spawn_presence_sweeper, typing_handler};
+use replay::new_instance_id;
+use sqlx::{postgres::PgPoolOptions, PgPool};
 use sse::sse_handler;
-use std::{ops::Deref, sync::Arc};
-use tokio::sync::broadcast;
+use std::{
+    ops::Deref,
+    sync::{atomic::AtomicU64, Arc, Mutex},
+};
 use tower_http::cors::{self, CorsLayer};
+use ws::ws_handler;
 
 pub use config::AppConfig;
 pub use error::AppError;
-pub use notif::AppEvent;
+pub use health::{Health, ListenerHealth};
+pub use metrics::Metrics;
+pub use notif::{
+    AppEvent, Mentioned, MessageDeleted, PinChanged, ReactionChanged, ReplyCountChanged,
+};
+pub use presence::{PresenceChanged, PresenceMap, PresenceStatus, Typing, TypingMap};
+pub use replay::{EventEnvelope, UserChannel};
 
-pub type UserMap = Arc<DashMap<u64, broadcast::Sender<Arc<AppEvent>>>>;
+pub type UserMap = Arc<DashMap<u64, Arc<UserChannel>>>;
 
 #[derive(Clone)]
 pub struct AppState(Arc<AppStateInner>);
@@ -32,7 +69,16 @@ pub struct AppState(Arc<AppStateInner>);
 pub struct AppStateInner {
     pub config: AppConfig,
     users: UserMap,
+    // tells the event ids sent by this instance from the ones sent by others
+    instance_id: u64,
+    // id of the next event sent to any user
+    event_id: AtomicU64,
+    // typing and presence reported by clients, expired by the presence sweeper
+    presence: PresenceMap,
+    typing: TypingMap,
     dk: DecodingKey,
+    pool: PgPool,
+    listener: Mutex<ListenerHealth>,
 }
 
 const INDEX_HTML: &str = include_str!("../index.html");
@@ -40,6 +86,7 @@ const INDEX_HTML: &str = include_str!("../index.html");
 pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
     let state = AppState::new(config);
     notif::setup_pg_listener(state.clone()).await?;
+    spawn_presence_sweeper(state.clone());
 
     let cors = CorsLayer::new()
         // allow `GET` and `POST` when accessing the resource
@@ -55,9 +102,14 @@ pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
 
     let app = Router::new()
         .route("/events", get(sse_handler))
+        .route("/ws", get(ws_handler))
+        .route("/typing", post(typing_handler))
+        .route("/presence", post(presence_handler))
         .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
         .layer(cors)
         .route("/", get(index_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/health", get(health_handler))
         .with_state(state);
 
     Ok(app)
@@ -87,6 +139,19 @@ impl AppState {
     pub fn new(config: AppConfig) -> Self {
         let dk = DecodingKey::load(&config.auth.pk).expect("Failed to load public key");
         let users = Arc::new(DashMap::new());
-        Self(Arc::new(AppStateInner { config, dk, users }))
+        let pool = PgPoolOptions::new()
+            .connect_lazy(&config.server.db_url)
+            .expect("Failed to parse db url");
+        Self(Arc::new(AppStateInner {
+            config,
+            users,
+            instance_id: new_instance_id(),
+            event_id: AtomicU64::new(1),
+            presence: Arc::new(DashMap::new()),
+            typing: Arc::new(DashMap::new()),
+            dk,
+            pool,
+            listener: Mutex::new(ListenerHealth::default()),
+        }))
     }
 }
@@ -0,0 +1,44 @@
+use crate::AppState;
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+
+/// Connections and memory held by this instance
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    /// users with at least one connection open
+    pub connected_users: usize,
+    /// open SSE and WebSocket connections
+    pub connections: usize,
+    /// users with a channel, a channel is dropped with the last connection of its user
+    pub channels: usize,
+    /// events kept for replay over all channels
+    pub buffered_events: usize,
+    /// users reported online or away to this instance
+    pub present_users: usize,
+    pub typing_users: usize,
+}
+
+pub(crate) async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.metrics())
+}
+
+impl AppState {
+    pub fn metrics(&self) -> Metrics {
+        let mut metrics = Metrics {
+            channels: self.users.len(),
+            present_users: self.presence.len(),
+            typing_users: self.typing.len(),
+            ..Default::default()
+        };
+        for channel in self.users.iter() {
+            let connections = channel.connections();
+            if connections > 0 {
+                metrics.connected_users += 1;
+                metrics.connections += connections;
+            }
+            metrics.buffered_events += channel.buffered_events();
+        }
+        metrics
+    }
+}
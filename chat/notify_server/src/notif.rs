@@ -1,12 +1,32 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
-use crate::AppState;
-use chat_core::{Chat, Message};
-use futures::StreamExt;
+use crate::{
+    presence::{PresenceChanged, Typing},
+    AppError, AppState,
+};
+use anyhow::Context;
+use chat_core::{Chat, ChatRead, Message};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgListener;
 use tracing::{info, warn};
 
+const CHANNELS: [&str; 10] = [
+    "chat_updated",
+    "chat_message_created",
+    "chat_message_updated",
+    "chat_message_deleted",
+    "chat_reply_count_updated",
+    "chat_message_reacted",
+    "chat_read_updated",
+    "chat_message_mentioned",
+    "chat_message_pinned",
+    "chat_user_event",
+];
+// backoff between attempts to reconnect the listener
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "event")]
 pub enum AppEvent {
@@ -14,6 +34,67 @@ pub enum AppEvent {
     AddToChat(Chat),
     RemoveFromChat(Chat),
     NewMessage(Message),
+    MessageUpdated(Message),
+    MessageDeleted(MessageDeleted),
+    ReplyCountChanged(ReplyCountChanged),
+    ReactionChanged(ReactionChanged),
+    ChatRead(ChatRead),
+    Mentioned(Mentioned),
+    PinChanged(PinChanged),
+    Typing(Typing),
+    PresenceChanged(PresenceChanged),
+    /// the client missed events and should reload its state, e.g. it resumed from an
+    /// event id another notify_server instance sent
+    Resync,
+}
+
+/// A message is deleted, only its ids are sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct MessageDeleted {
+    pub id: i64,
+    pub chat_id: i64,
+    pub thread_root_id: Option<i64>,
+}
+
+/// A reply is added to or deleted from a thread
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ReplyCountChanged {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub reply_count: i32,
+}
+
+/// A user added or removed an emoji reaction on a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ReactionChanged {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub emoji: String,
+    pub added: bool,
+}
+
+/// A message is pinned or unpinned in a chat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct PinChanged {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub pinned: bool,
+}
+
+/// A user is mentioned in a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Mentioned {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub sender_id: i64,
+    pub thread_root_id: Option<i64>,
 }
 
 #[derive(Debug)]
@@ -38,79 +119,360 @@ struct ChatMessageCreated {
     members: Vec<i64>,
 }
 
+// pg_notify('chat_message_deleted', json_build_object('message', ..., 'members', USERS)::text);
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessageDeleted {
+    message: MessageDeleted,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_reply_count_updated', json_build_object('thread', ..., 'members', USERS)::text);
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatReplyCountUpdated {
+    thread: ReplyCountChanged,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_message_reacted', json_build_object('reaction', ..., 'members', USERS)::text);
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessageReacted {
+    reaction: ReactionChanged,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_message_pinned', json_build_object('pin', ..., 'members', USERS)::text);
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessagePinned {
+    pin: PinChanged,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_message_mentioned', json_build_object('mention', ..., 'users', USERS)::text);
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessageMentioned {
+    mention: Mentioned,
+    users: Vec<i64>,
+}
+
+// pg_notify('chat_user_event', json_build_object('event', ..., 'users', USERS)::text);
+// sent by notify_server itself, so events like typing reach users connected to other instances
+#[derive(Debug, Serialize, Deserialize)]
+struct UserEvent {
+    event: AppEvent,
+    users: Vec<i64>,
+}
+
 pub async fn setup_pg_listener(state: AppState) -> anyhow::Result<()> {
-    let mut listener = PgListener::connect(&state.config.server.db_url).await?;
-    listener.listen("chat_updated").await?;
-    listener.listen("chat_message_created").await?;
-
-    let mut stream = listener.into_stream();
-
-    tokio::spawn(async move {
-        while let Some(Ok(notif)) = stream.next().await {
-            info!("Received notification: {:?}", notif);
-            let notification = Notification::load(notif.channel(), notif.payload())?;
-            let users = &state.users;
-            for user_id in notification.user_ids {
-                if let Some(tx) = users.get(&user_id) {
-                    info!("Sending notification to user {}", user_id);
-                    if let Err(e) = tx.send(notification.event.clone()) {
-                        warn!("Failed to send notification to user {}: {}", user_id, e);
-                    }
+    // fail at startup if the db can't be reached, it's reconnected afterwards
+    let listener = connect_pg_listener(&state.config.server.db_url).await?;
+    state.update_listener_health(|h| {
+        h.connected = true;
+        h.since = Some(Utc::now());
+    });
+    tokio::spawn(run_pg_listener(state, listener));
+
+    Ok(())
+}
+
+async fn connect_pg_listener(db_url: &str) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect(db_url).await?;
+    listener.listen_all(CHANNELS).await?;
+    Ok(listener)
+}
+
+async fn run_pg_listener(state: AppState, mut listener: PgListener) {
+    loop {
+        let e = receive_notifications(&state, &mut listener).await;
+        // notifications sent until reconnected are missed
+        warn!("Lost connection of pg listener: {}", e);
+        state.update_listener_health(|h| {
+            h.connected = false;
+            h.since = Some(Utc::now());
+            h.last_error = Some(e.to_string());
+        });
+        listener = reconnect_pg_listener(&state).await;
+    }
+}
+
+// returns why the connection is lost
+async fn receive_notifications(state: &AppState, listener: &mut PgListener) -> anyhow::Error {
+    loop {
+        let notif = match listener.try_recv().await {
+            Ok(Some(notif)) => notif,
+            Ok(None) => return anyhow::anyhow!("connection closed"),
+            Err(e) => return e.into(),
+        };
+        info!("Received notification: {:?}", notif);
+        state.update_listener_health(|h| h.last_notification_at = Some(Utc::now()));
+
+        // a bad notification mustn't stop the others from being delivered
+        match Notification::load(notif.channel(), notif.payload()) {
+            Ok(notifications) => {
+                for notification in notifications {
+                    state.send_event(notification.user_ids, notification.event);
                 }
             }
+            Err(e) => {
+                warn!("Failed to load notification {:?}: {}", notif, e);
+                state.update_listener_health(|h| h.failed_notifications += 1);
+            }
         }
-        Ok::<_, anyhow::Error>(())
-    });
+    }
+}
 
-    Ok(())
+async fn reconnect_pg_listener(state: &AppState) -> PgListener {
+    let mut backoff = RECONNECT_MIN_BACKOFF;
+    loop {
+        tokio::time::sleep(backoff).await;
+        match connect_pg_listener(&state.config.server.db_url).await {
+            Ok(listener) => {
+                info!("Reconnected pg listener");
+                state.update_listener_health(|h| {
+                    h.connected = true;
+                    h.since = Some(Utc::now());
+                    h.reconnects += 1;
+                });
+                return listener;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to reconnect pg listener, retry in {:?}: {}",
+                    backoff, e
+                );
+                state.update_listener_health(|h| h.last_error = Some(e.to_string()));
+                backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+impl AppState {
+    /// Publish an event through Postgres, every instance sends it to the users connected
+    /// to it (see `chat_user_event`).
+    pub(crate) async fn publish_event(
+        &self,
+        user_ids: impl IntoIterator<Item = u64>,
+        event: AppEvent,
+    ) -> Result<(), AppError> {
+        let payload = UserEvent {
+            event,
+            users: user_ids.into_iter().map(|v| v as i64).collect(),
+        };
+        if payload.users.is_empty() {
+            return Ok(());
+        }
+        sqlx::query("SELECT pg_notify('chat_user_event', $1)")
+            .bind(serde_json::to_string(&payload).expect("Failed to serialize event"))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 }
 
 impl Notification {
-    fn load(r#type: &str, payload: &str) -> anyhow::Result<Self> {
+    fn load(r#type: &str, payload: &str) -> anyhow::Result<Vec<Self>> {
         match r#type {
             "chat_updated" => {
                 let payload: ChatUpdated = serde_json::from_str(payload)?;
                 info!("ChatUpdated: {:?}", payload);
-                let user_ids =
-                    get_affected_chat_user_ids(payload.old.as_ref(), payload.new.as_ref());
-                let event = match payload.op.as_str() {
-                    "INSERT" => AppEvent::NewChat(payload.new.expect("new should exist")),
-                    "UPDATE" => AppEvent::AddToChat(payload.new.expect("new should exist")),
-                    "DELETE" => AppEvent::RemoveFromChat(payload.old.expect("old should exist")),
-                    _ => return Err(anyhow::anyhow!("Invalid operation")),
-                };
-                Ok(Self {
-                    user_ids,
-                    event: Arc::new(event),
-                })
+                match payload.op.as_str() {
+                    "INSERT" => {
+                        let chat = payload.new.context("new should exist")?;
+                        Ok(vec![Self::new(
+                            chat_user_ids(&chat),
+                            AppEvent::NewChat(chat),
+                        )])
+                    }
+                    "UPDATE" => {
+                        let old = payload.old.context("old should exist")?;
+                        let new = payload.new.context("new should exist")?;
+                        let (removed, current) = get_affected_chat_user_ids(&old, &new);
+                        let mut ret = Vec::new();
+                        if !current.is_empty() {
+                            ret.push(Self::new(current, AppEvent::AddToChat(new.clone())));
+                        }
+                        // removed users aren't allowed to see the chat as updated
+                        if !removed.is_empty() {
+                            ret.push(Self::new(removed, AppEvent::RemoveFromChat(old)));
+                        }
+                        Ok(ret)
+                    }
+                    "DELETE" => {
+                        let chat = payload.old.context("old should exist")?;
+                        Ok(vec![Self::new(
+                            chat_user_ids(&chat),
+                            AppEvent::RemoveFromChat(chat),
+                        )])
+                    }
+                    _ => Err(anyhow::anyhow!("Invalid operation")),
+                }
             }
             "chat_message_created" => {
                 let payload: ChatMessageCreated = serde_json::from_str(payload)?;
                 let user_ids = payload.members.iter().map(|v| *v as u64).collect();
-                Ok(Self {
+                Ok(vec![Self::new(
+                    user_ids,
+                    AppEvent::NewMessage(payload.message),
+                )])
+            }
+            "chat_message_updated" => {
+                // same payload as chat_message_created
+                let payload: ChatMessageCreated = serde_json::from_str(payload)?;
+                let user_ids = payload.members.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(
+                    user_ids,
+                    AppEvent::MessageUpdated(payload.message),
+                )])
+            }
+            "chat_message_deleted" => {
+                let payload: ChatMessageDeleted = serde_json::from_str(payload)?;
+                let user_ids = payload.members.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(
+                    user_ids,
+                    AppEvent::MessageDeleted(payload.message),
+                )])
+            }
+            "chat_reply_count_updated" => {
+                let payload: ChatReplyCountUpdated = serde_json::from_str(payload)?;
+                let user_ids = payload.members.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(
                     user_ids,
-                    event: Arc::new(AppEvent::NewMessage(payload.message)),
-                })
+                    AppEvent::ReplyCountChanged(payload.thread),
+                )])
+            }
+            "chat_message_reacted" => {
+                let payload: ChatMessageReacted = serde_json::from_str(payload)?;
+                let user_ids = payload.members.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(
+                    user_ids,
+                    AppEvent::ReactionChanged(payload.reaction),
+                )])
+            }
+            "chat_read_updated" => {
+                // only the user's own devices care about their read position
+                let payload: ChatRead = serde_json::from_str(payload)?;
+                let user_ids = HashSet::from([payload.user_id as u64]);
+                Ok(vec![Self::new(user_ids, AppEvent::ChatRead(payload))])
+            }
+            "chat_message_mentioned" => {
+                // only sent to the mentioned users, on top of the message events
+                let payload: ChatMessageMentioned = serde_json::from_str(payload)?;
+                let user_ids = payload.users.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(
+                    user_ids,
+                    AppEvent::Mentioned(payload.mention),
+                )])
+            }
+            "chat_message_pinned" => {
+                let payload: ChatMessagePinned = serde_json::from_str(payload)?;
+                let user_ids = payload.members.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(user_ids, AppEvent::PinChanged(payload.pin))])
+            }
+            "chat_user_event" => {
+                let payload: UserEvent = serde_json::from_str(payload)?;
+                let user_ids = payload.users.iter().map(|v| *v as u64).collect();
+                Ok(vec![Self::new(user_ids, payload.event)])
             }
             _ => Err(anyhow::anyhow!("Invalid notification type")),
         }
     }
-}
 
-fn get_affected_chat_user_ids(old: Option<&Chat>, new: Option<&Chat>) -> HashSet<u64> {
-    match (old, new) {
-        (Some(old), Some(new)) => {
-            // diff old/new members, if identical, no need to notify, otherwise notify the union of both
-            let old_user_ids: HashSet<_> = old.members.iter().map(|v| *v as u64).collect();
-            let new_user_ids: HashSet<_> = new.members.iter().map(|v| *v as u64).collect();
-            if old_user_ids == new_user_ids {
-                HashSet::new()
-            } else {
-                old_user_ids.union(&new_user_ids).copied().collect()
-            }
+    fn new(user_ids: HashSet<u64>, event: AppEvent) -> Self {
+        Self {
+            user_ids,
+            event: Arc::new(event),
         }
-        (Some(old), None) => old.members.iter().map(|v| *v as u64).collect(),
-        (None, Some(new)) => new.members.iter().map(|v| *v as u64).collect(),
-        _ => HashSet::new(),
+    }
+}
+
+fn chat_user_ids(chat: &Chat) -> HashSet<u64> {
+    chat.members.iter().map(|v| *v as u64).collect()
+}
+
+// diff old/new members: returns (removed users, users still in the chat). Members get the
+// chat on any change, e.g. a rename. If nothing changed, no need to notify anyone.
+fn get_affected_chat_user_ids(old: &Chat, new: &Chat) -> (HashSet<u64>, HashSet<u64>) {
+    if old == new {
+        return (HashSet::new(), HashSet::new());
+    }
+    let old_user_ids = chat_user_ids(old);
+    let new_user_ids = chat_user_ids(new);
+    let removed = old_user_ids.difference(&new_user_ids).copied().collect();
+    (removed, new_user_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn load_notification_with_bad_payload_should_fail() {
+        assert!(Notification::load("chat_message_deleted", "not json").is_err());
+        assert!(Notification::load("chat_message_deleted", r#"{"members": [1]}"#).is_err());
+        assert!(Notification::load("chat_updated", r#"{"op": "UPDATE", "old": null}"#).is_err());
+        assert!(Notification::load("unknown_channel", "{}").is_err());
+    }
+
+    #[test]
+    fn load_notification_should_work() -> anyhow::Result<()> {
+        let payload = json!({
+            "message": {"id": 2, "chat_id": 1, "thread_root_id": null},
+            "members": [1, 2, 3],
+        });
+        let notifications = Notification::load("chat_message_deleted", &payload.to_string())?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2, 3]));
+        let AppEvent::MessageDeleted(message) = notifications[0].event.as_ref() else {
+            panic!("MessageDeleted expected");
+        };
+        assert_eq!((message.id, message.chat_id), (2, 1));
+
+        let payload = json!({
+            "thread": {"chat_id": 1, "message_id": 2, "reply_count": 3},
+            "members": [1, 2],
+        });
+        let notifications = Notification::load("chat_reply_count_updated", &payload.to_string())?;
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert!(matches!(
+            notifications[0].event.as_ref(),
+            AppEvent::ReplyCountChanged(thread) if thread.reply_count == 3
+        ));
+
+        // members get a renamed chat
+        let chat = json!({
+            "id": 1,
+            "ws_id": 1,
+            "name": "general",
+            "type": "public_channel",
+            "members": [1, 2],
+            "agents": [],
+            "created_at": "2024-11-07T09:53:23.633378+00:00",
+            "created_by": null,
+        });
+        let mut renamed = chat.clone();
+        renamed["name"] = "random".into();
+        let payload = json!({"op": "UPDATE", "old": chat, "new": renamed});
+        let notifications = Notification::load("chat_updated", &payload.to_string())?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert!(matches!(
+            notifications[0].event.as_ref(),
+            AppEvent::AddToChat(chat) if chat.name.as_deref() == Some("random")
+        ));
+
+        // removed members get the chat as it was before, not its new members
+        let mut updated = chat.clone();
+        updated["members"] = json!([1, 3]);
+        let payload = json!({"op": "UPDATE", "old": chat, "new": updated});
+        let notifications = Notification::load("chat_updated", &payload.to_string())?;
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 3]));
+        assert_eq!(notifications[1].user_ids, HashSet::from([2]));
+        assert!(matches!(
+            notifications[1].event.as_ref(),
+            AppEvent::RemoveFromChat(chat) if chat.members == [1, 2]
+        ));
+        Ok(())
     }
 }
@@ -0,0 +1,310 @@
+use crate::{AppError, AppEvent, AppState};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use chat_core::User;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+// a user not reporting presence within this time goes offline, clients heartbeat
+// more often than that
+const PRESENCE_TTL: Duration = Duration::from_secs(60);
+// typing stops if not reported again within this time
+const TYPING_TTL: Duration = Duration::from_secs(5);
+// a reported stop is only published if typing doesn't start again within this time
+const TYPING_STOP_DELAY: Duration = Duration::from_secs(1);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// user id => presence
+pub type PresenceMap = Arc<DashMap<u64, Presence>>;
+/// (chat id, user id) => when typing expires
+pub type TypingMap = Arc<DashMap<(u64, u64), Instant>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Presence {
+    status: PresenceStatus,
+    expires_at: Instant,
+}
+
+/// A user started or stopped typing in a chat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Typing {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub typing: bool,
+}
+
+/// A user went online, away or offline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceChanged {
+    pub user_id: i64,
+    pub status: PresenceStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReportTyping {
+    #[serde(alias = "chat_id")]
+    pub(crate) chat_id: u64,
+    pub(crate) typing: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportPresence {
+    pub(crate) status: PresenceStatus,
+}
+
+/// Report the user is typing (or stopped typing) in a chat.
+pub(crate) async fn typing_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<ReportTyping>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .set_typing(input.chat_id, user.id as _, input.typing)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Report presence of the user, clients send it periodically as a heartbeat.
+pub(crate) async fn presence_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<ReportPresence>,
+) -> Result<impl IntoResponse, AppError> {
+    state.set_presence(user.id as _, input.status).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Expire typing and presence which are not reported again in time.
+pub(crate) fn spawn_presence_sweeper(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            state.expire_presence().await;
+        }
+    });
+}
+
+impl AppState {
+    /// Update the presence of a user, users sharing a chat with them are notified if
+    /// the status changed. Presence is mirrored to `user_presence` for chat_server.
+    pub(crate) async fn set_presence(
+        &self,
+        user_id: u64,
+        status: PresenceStatus,
+    ) -> Result<(), AppError> {
+        let old = match status {
+            PresenceStatus::Offline => self.presence.remove(&user_id).map(|(_, p)| p.status),
+            _ => {
+                let presence = Presence {
+                    status,
+                    expires_at: Instant::now() + PRESENCE_TTL,
+                };
+                self.presence.insert(user_id, presence).map(|p| p.status)
+            }
+        };
+        self.save_presence(user_id, status).await?;
+
+        if old.unwrap_or(PresenceStatus::Offline) != status {
+            self.notify_presence(user_id, status).await?;
+        }
+        Ok(())
+    }
+
+    /// Start or stop typing in a chat, other members are notified when it changes. Only
+    /// starting to type hits the database, a stop is debounced in memory and published by
+    /// the sweeper, so quick stop / start toggles are never sent.
+    pub(crate) async fn set_typing(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        typing: bool,
+    ) -> Result<(), AppError> {
+        let key = (chat_id, user_id);
+        // already typing, the membership was checked when it started
+        if let Some(mut expires_at) = self.typing.get_mut(&key) {
+            let ttl = if typing {
+                TYPING_TTL
+            } else {
+                TYPING_STOP_DELAY
+            };
+            *expires_at = Instant::now() + ttl;
+            return Ok(());
+        }
+        if !typing {
+            return Ok(());
+        }
+
+        let members = self.fetch_chat_members(chat_id).await?;
+        if !members.contains(&(user_id as i64)) {
+            return Err(AppError::NotChatMemberError { user_id, chat_id });
+        }
+        if self
+            .typing
+            .insert(key, Instant::now() + TYPING_TTL)
+            .is_none()
+        {
+            self.notify_typing(chat_id, user_id, true, &members).await?;
+        }
+        Ok(())
+    }
+
+    async fn expire_presence(&self) {
+        let now = Instant::now();
+
+        let expired: Vec<_> = self
+            .typing
+            .iter()
+            .filter(|e| *e.value() <= now)
+            .map(|e| *e.key())
+            .collect();
+        for (chat_id, user_id) in expired {
+            if self
+                .typing
+                .remove_if(&(chat_id, user_id), |_, t| *t <= now)
+                .is_none()
+            {
+                continue;
+            }
+            let ret = match self.fetch_chat_members(chat_id).await {
+                Ok(members) => self.notify_typing(chat_id, user_id, false, &members).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = ret {
+                warn!("failed to notify typing of user {}: {}", user_id, e);
+            }
+        }
+
+        let expired: Vec<_> = self
+            .presence
+            .iter()
+            .filter(|e| e.value().expires_at <= now)
+            .map(|e| *e.key())
+            .collect();
+        for user_id in expired {
+            if self
+                .presence
+                .remove_if(&user_id, |_, p| p.expires_at <= now)
+                .is_none()
+            {
+                continue;
+            }
+            // the row in `user_presence` expires by itself, unless the user keeps reporting
+            // to another instance
+            let ret = match self.is_online_elsewhere(user_id).await {
+                Ok(true) => Ok(()),
+                Ok(false) => self.notify_presence(user_id, PresenceStatus::Offline).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = ret {
+                warn!("failed to notify presence of user {}: {}", user_id, e);
+            }
+        }
+    }
+
+    async fn notify_typing(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        typing: bool,
+        members: &[i64],
+    ) -> Result<(), AppError> {
+        let event = AppEvent::Typing(Typing {
+            chat_id: chat_id as _,
+            user_id: user_id as _,
+            typing,
+        });
+        let user_ids = members.iter().map(|v| *v as u64).filter(|v| *v != user_id);
+        self.publish_event(user_ids, event).await
+    }
+
+    async fn notify_presence(&self, user_id: u64, status: PresenceStatus) -> Result<(), AppError> {
+        // the user's other devices want it as well
+        let user_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT m
+            FROM chats, unnest(members) AS m
+            WHERE members @> ARRAY[$1]
+            "#,
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let event = AppEvent::PresenceChanged(PresenceChanged {
+            user_id: user_id as _,
+            status,
+        });
+        let user_ids = user_ids.into_iter().map(|v| v as u64);
+        self.publish_event(user_ids, event).await
+    }
+
+    async fn save_presence(&self, user_id: u64, status: PresenceStatus) -> Result<(), AppError> {
+        let status = match status {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Away => "away",
+            PresenceStatus::Offline => {
+                sqlx::query("DELETE FROM user_presence WHERE user_id = $1")
+                    .bind(user_id as i64)
+                    .execute(&self.pool)
+                    .await?;
+                return Ok(());
+            }
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO user_presence (user_id, status, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            ON CONFLICT (user_id) DO UPDATE
+            SET status = EXCLUDED.status, expires_at = EXCLUDED.expires_at, updated_at = now()
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(status)
+        .bind(PRESENCE_TTL.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // the row saved by this instance expires about now, a later heartbeat to another
+    // instance pushes it further out
+    async fn is_online_elsewhere(&self, user_id: u64) -> Result<bool, AppError> {
+        let online = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM user_presence
+                WHERE user_id = $1 AND expires_at > now() + interval '5 seconds'
+            )
+            "#,
+        )
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(online)
+    }
+
+    pub(crate) async fn fetch_chat_members(&self, chat_id: u64) -> Result<Vec<i64>, AppError> {
+        let members: Option<Vec<i64>> =
+            sqlx::query_scalar("SELECT members FROM chats WHERE id = $1")
+                .bind(chat_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(members.unwrap_or_default())
+    }
+}
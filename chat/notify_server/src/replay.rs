@@ -0,0 +1,411 @@
+use crate::{AppEvent, AppState, UserMap};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+// the channel only carries events to connections keeping up, a connection lagging behind
+// catches up from the replay buffer
+const CHANNEL_CAPACITY: usize = 64;
+// events kept per user for connections to resume from after lagging behind or reconnecting
+// while another connection of the user is open
+const REPLAY_CAPACITY: usize = 256;
+
+/// An event with its id in the stream of a user
+#[derive(Debug)]
+pub struct EventEnvelope {
+    pub id: u64,
+    pub event: Arc<AppEvent>,
+}
+
+/// Event stream of a user, shared by all their connections and dropped with the last one.
+/// Recent events are kept in a bounded buffer, so they can be replayed to connections
+/// resuming from an event id.
+#[derive(Debug)]
+pub struct UserChannel {
+    tx: broadcast::Sender<Arc<EventEnvelope>>,
+    buffer: Mutex<ReplayBuffer>,
+}
+
+#[derive(Debug)]
+struct ReplayBuffer {
+    events: VecDeque<Arc<EventEnvelope>>,
+    // id of the latest event sent before the channel was created, the user may have
+    // missed events up to it
+    since: u64,
+    // id of the latest event pushed out of the buffer
+    evicted: u64,
+}
+
+/// A connection's view of the stream of a user, replayed events come first. The channel
+/// of the user is dropped with their last subscription.
+pub(crate) struct Subscription {
+    user_id: u64,
+    users: UserMap,
+    channel: Arc<UserChannel>,
+    rx: broadcast::Receiver<Arc<EventEnvelope>>,
+    pending: VecDeque<Arc<EventEnvelope>>,
+    last_id: u64,
+}
+
+/// Where a connection resumes the stream of its user from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResumeFrom {
+    /// a new connection, only events sent from now on
+    Latest,
+    /// the last event the client got from this instance
+    Event(u64),
+    /// an event id this instance didn't send, e.g. from another instance or a previous run
+    Unknown,
+}
+
+/// `lastEventId` in the query, for clients which can't set the `Last-Event-ID` header
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ResumeParams {
+    last_event_id: Option<String>,
+}
+
+/// Every instance (and every run of it) picks its own id, event ids sent to clients are
+/// `{instance id}-{seq}` so an instance can tell the ids it didn't send.
+pub(crate) fn new_instance_id() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch");
+    now.as_micros() as u64
+}
+
+pub(crate) fn format_event_id(instance_id: u64, id: u64) -> String {
+    format!("{instance_id}-{id}")
+}
+
+impl ResumeParams {
+    /// The event to resume after, the `Last-Event-ID` header sent by reconnecting
+    /// `EventSource`s takes precedence.
+    pub(crate) fn resume_from(&self, headers: &HeaderMap, instance_id: u64) -> ResumeFrom {
+        let last_event_id = headers
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+            .or(self.last_event_id.as_deref());
+        let Some(last_event_id) = last_event_id else {
+            return ResumeFrom::Latest;
+        };
+        match last_event_id.split_once('-') {
+            Some((instance, id)) if instance.parse() == Ok(instance_id) => {
+                id.parse().map_or(ResumeFrom::Unknown, ResumeFrom::Event)
+            }
+            _ => ResumeFrom::Unknown,
+        }
+    }
+}
+
+impl AppState {
+    /// Subscribe to the events of a user, resuming from `from`.
+    pub(crate) fn subscribe(&self, user_id: u64, from: ResumeFrom) -> Subscription {
+        // subscribe while holding the entry, so the last subscription of the user can't
+        // drop the channel meanwhile
+        let entry = self.users.entry(user_id).or_insert_with(|| {
+            let since = self.event_id.load(Ordering::Relaxed) - 1;
+            Arc::new(UserChannel::new(since))
+        });
+        entry
+            .value()
+            .clone()
+            .subscribe(self.users.clone(), user_id, from)
+    }
+
+    /// Send an event to the users connected to this server.
+    pub(crate) fn send_event(&self, user_ids: impl IntoIterator<Item = u64>, event: Arc<AppEvent>) {
+        for user_id in user_ids {
+            if let Some(channel) = self.users.get(&user_id) {
+                info!("Sending notification to user {}", user_id);
+                channel.send(&self.event_id, event.clone());
+            }
+        }
+    }
+}
+
+impl UserChannel {
+    fn new(since: u64) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let buffer = ReplayBuffer {
+            events: VecDeque::new(),
+            since,
+            evicted: since,
+        };
+        Self {
+            tx,
+            buffer: Mutex::new(buffer),
+        }
+    }
+
+    pub(crate) fn connections(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    pub(crate) fn buffered_events(&self) -> usize {
+        self.buffer
+            .lock()
+            .expect("replay buffer poisoned")
+            .events
+            .len()
+    }
+
+    fn send(&self, event_id: &AtomicU64, event: Arc<AppEvent>) {
+        // ids are taken under the lock so they are in order within the stream
+        let mut buffer = self.buffer.lock().expect("replay buffer poisoned");
+        let id = event_id.fetch_add(1, Ordering::Relaxed);
+        let envelope = Arc::new(EventEnvelope { id, event });
+        if buffer.events.len() == REPLAY_CAPACITY {
+            if let Some(evicted) = buffer.events.pop_front() {
+                buffer.evicted = evicted.id;
+            }
+        }
+        buffer.events.push_back(envelope.clone());
+        // fails if no connection of the user is open
+        let _ = self.tx.send(envelope);
+    }
+
+    fn subscribe(self: Arc<Self>, users: UserMap, user_id: u64, from: ResumeFrom) -> Subscription {
+        // subscribe under the lock, so the events are either replayed or received, not both
+        let buffer = self.buffer.lock().expect("replay buffer poisoned");
+        let rx = self.tx.subscribe();
+        let last_id = buffer.latest_id();
+        let pending = match from {
+            ResumeFrom::Latest => VecDeque::new(),
+            ResumeFrom::Event(id) => buffer.replay_after(user_id, id),
+            ResumeFrom::Unknown => {
+                warn!("User {} resumed from an unknown event id", user_id);
+                buffer.resync()
+            }
+        };
+        drop(buffer);
+
+        Subscription {
+            user_id,
+            users,
+            channel: self,
+            rx,
+            pending,
+            last_id,
+        }
+    }
+
+    fn replay_after(&self, user_id: u64, id: u64) -> VecDeque<Arc<EventEnvelope>> {
+        let buffer = self.buffer.lock().expect("replay buffer poisoned");
+        buffer.replay_after(user_id, id)
+    }
+}
+
+impl ReplayBuffer {
+    fn latest_id(&self) -> u64 {
+        self.events.back().map_or(self.since, |e| e.id)
+    }
+
+    // events after `id`, or a `Resync` if some of them are no longer buffered
+    fn replay_after(&self, user_id: u64, id: u64) -> VecDeque<Arc<EventEnvelope>> {
+        if id < self.evicted || id > self.latest_id() {
+            warn!(
+                "Events of user {} after {} are no longer buffered, resyncing",
+                user_id, id
+            );
+            return self.resync();
+        }
+        self.events.iter().filter(|e| e.id > id).cloned().collect()
+    }
+
+    // tells the client it missed events, so it reloads its state. It carries the latest id
+    // so the client could resume from it later.
+    fn resync(&self) -> VecDeque<Arc<EventEnvelope>> {
+        let envelope = EventEnvelope {
+            id: self.latest_id(),
+            event: Arc::new(AppEvent::Resync),
+        };
+        VecDeque::from([Arc::new(envelope)])
+    }
+}
+
+impl Subscription {
+    /// The next event, `None` if the channel is closed.
+    pub(crate) async fn recv(&mut self) -> Option<Arc<EventEnvelope>> {
+        loop {
+            if let Some(envelope) = self.pending.pop_front() {
+                self.last_id = envelope.id;
+                return Some(envelope);
+            }
+            match self.rx.recv().await {
+                // already replayed
+                Ok(envelope) if envelope.id <= self.last_id => continue,
+                Ok(envelope) => {
+                    self.last_id = envelope.id;
+                    return Some(envelope);
+                }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(
+                        "User {} lagged behind by {} events, replaying from buffer",
+                        self.user_id, n
+                    );
+                    self.pending = self.channel.replay_after(self.user_id, self.last_id);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // the receiver of this subscription is still counted
+        let removed = self.users.remove_if(&self.user_id, |_, channel| {
+            Arc::ptr_eq(channel, &self.channel) && channel.connections() == 1
+        });
+        if removed.is_some() {
+            info!("Dropping channel of user {}", self.user_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PinChanged;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn new_event(message_id: i64) -> Arc<AppEvent> {
+        Arc::new(AppEvent::PinChanged(PinChanged {
+            chat_id: 1,
+            message_id,
+            user_id: 1,
+            pinned: true,
+        }))
+    }
+
+    fn send_events(channel: &UserChannel, event_id: &AtomicU64, n: usize) {
+        for i in 0..n {
+            channel.send(event_id, new_event(i as _));
+        }
+    }
+
+    fn subscribe(channel: &Arc<UserChannel>, from: ResumeFrom) -> Subscription {
+        channel.clone().subscribe(UserMap::default(), 1, from)
+    }
+
+    // ids of the events received until none is left
+    async fn recv_ids(subscription: &mut Subscription) -> Vec<u64> {
+        let mut ids = Vec::new();
+        while let Ok(Some(envelope)) = timeout(Duration::from_millis(50), subscription.recv()).await
+        {
+            ids.push(envelope.id);
+        }
+        ids
+    }
+
+    async fn assert_resync(subscription: &mut Subscription, id: u64) {
+        let envelope = subscription.recv().await.expect("resync should be sent");
+        assert!(matches!(envelope.event.as_ref(), AppEvent::Resync));
+        assert_eq!(envelope.id, id);
+    }
+
+    #[test]
+    fn resume_from_should_only_accept_ids_of_this_instance() {
+        let params = ResumeParams::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(params.resume_from(&headers, 7), ResumeFrom::Latest);
+
+        headers.insert("last-event-id", "7-12".parse().unwrap());
+        assert_eq!(params.resume_from(&headers, 7), ResumeFrom::Event(12));
+        assert_eq!(params.resume_from(&headers, 8), ResumeFrom::Unknown);
+
+        // ids sent before instance ids were added
+        headers.insert("last-event-id", "12".parse().unwrap());
+        assert_eq!(params.resume_from(&headers, 7), ResumeFrom::Unknown);
+    }
+
+    #[tokio::test]
+    async fn subscription_should_resume_after_event_id() {
+        let channel = Arc::new(UserChannel::new(0));
+        let event_id = AtomicU64::new(1);
+        send_events(&channel, &event_id, 3);
+
+        // a new subscription only gets the events sent from now on
+        let mut latest = subscribe(&channel, ResumeFrom::Latest);
+        let mut resumed = subscribe(&channel, ResumeFrom::Event(1));
+        assert_eq!(recv_ids(&mut resumed).await, [2, 3]);
+
+        send_events(&channel, &event_id, 1);
+        assert_eq!(recv_ids(&mut resumed).await, [4]);
+        assert_eq!(recv_ids(&mut latest).await, [4]);
+    }
+
+    #[tokio::test]
+    async fn subscription_should_resync_if_events_are_missed() {
+        let channel = Arc::new(UserChannel::new(0));
+        let event_id = AtomicU64::new(1);
+        send_events(&channel, &event_id, REPLAY_CAPACITY + 10);
+        assert_eq!(channel.buffered_events(), REPLAY_CAPACITY);
+
+        // events 2 to 10 are no longer buffered
+        let latest = REPLAY_CAPACITY as u64 + 10;
+        let mut subscription = subscribe(&channel, ResumeFrom::Event(1));
+        assert_resync(&mut subscription, latest).await;
+
+        // an id of another instance can't be replayed from
+        let mut subscription = subscribe(&channel, ResumeFrom::Unknown);
+        assert_resync(&mut subscription, latest).await;
+
+        // a channel created after the event may have missed some
+        let channel = Arc::new(UserChannel::new(latest));
+        let mut subscription = subscribe(&channel, ResumeFrom::Event(5));
+        assert_resync(&mut subscription, latest).await;
+        send_events(&channel, &event_id, 1);
+        assert_eq!(recv_ids(&mut subscription).await, [latest + 1]);
+    }
+
+    #[tokio::test]
+    async fn lagging_subscription_should_catch_up_from_buffer() {
+        let channel = Arc::new(UserChannel::new(0));
+        let event_id = AtomicU64::new(1);
+        let mut subscription = subscribe(&channel, ResumeFrom::Latest);
+        assert_eq!(channel.connections(), 1);
+
+        // more events than the channel holds are sent before the connection reads any
+        send_events(&channel, &event_id, CHANNEL_CAPACITY + 10);
+        let ids = recv_ids(&mut subscription).await;
+        let expected: Vec<_> = (1..=CHANNEL_CAPACITY as u64 + 10).collect();
+        assert_eq!(ids, expected);
+
+        // and it keeps receiving new events without duplicates
+        send_events(&channel, &event_id, 1);
+        assert_eq!(
+            recv_ids(&mut subscription).await,
+            [CHANNEL_CAPACITY as u64 + 11]
+        );
+    }
+
+    #[test]
+    fn channel_should_be_dropped_with_last_subscription() {
+        let users = UserMap::default();
+        let channel = Arc::new(UserChannel::new(0));
+        users.insert(1, channel.clone());
+
+        let first = channel
+            .clone()
+            .subscribe(users.clone(), 1, ResumeFrom::Latest);
+        let second = channel
+            .clone()
+            .subscribe(users.clone(), 1, ResumeFrom::Latest);
+        drop(first);
+        assert!(users.contains_key(&1));
+        drop(second);
+        assert!(users.is_empty());
+    }
+}
@@ -1,44 +1,62 @@
-use crate::{AppEvent, AppState};
+use crate::{
+    presence::PresenceStatus,
+    replay::{format_event_id, ResumeParams},
+    AppEvent, AppState,
+};
 use axum::{
-    extract::State,
+    extract::{Query, State},
+    http::HeaderMap,
     response::{sse::Event, Sse},
     Extension,
 };
 use chat_core::User;
-use futures::Stream;
+use futures::{stream, Stream, StreamExt};
 use std::{convert::Infallible, time::Duration};
-use tokio::sync::broadcast;
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
-use tracing::{debug, info};
-
-const CHANNEL_CAPACITY: usize = 256;
+use tracing::{debug, info, warn};
 
 pub(crate) async fn sse_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
+    Query(params): Query<ResumeParams>,
+    headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let user_id = user.id as u64;
-    let users = &state.users;
+    let from = params.resume_from(&headers, state.instance_id);
+    let subscription = state.subscribe(user_id, from);
+    info!("User {} subscribed from {:?}", user_id, from);
+    // connecting means online, clients keep it with heartbeats afterwards
+    if let Err(e) = state.set_presence(user_id, PresenceStatus::Online).await {
+        warn!("failed to set presence of user {}: {}", user_id, e);
+    }
 
-    let rx = if let Some(tx) = users.get(&user_id) {
-        tx.subscribe()
-    } else {
-        let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
-        state.users.insert(user_id, tx);
-        rx
-    };
-    info!("User {} subscribed", user_id);
-
-    let stream = BroadcastStream::new(rx).filter_map(|v| v.ok()).map(|v| {
-        let name = match v.as_ref() {
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let envelope = subscription.recv().await?;
+        Some((envelope, subscription))
+    });
+    let instance_id = state.instance_id;
+    let stream = events.map(move |envelope| {
+        let name = match envelope.event.as_ref() {
             AppEvent::NewChat(_) => "NewChat",
             AppEvent::AddToChat(_) => "AddToChat",
             AppEvent::RemoveFromChat(_) => "RemoveFromChat",
             AppEvent::NewMessage(_) => "NewMessage",
+            AppEvent::MessageUpdated(_) => "MessageUpdated",
+            AppEvent::MessageDeleted(_) => "MessageDeleted",
+            AppEvent::ReplyCountChanged(_) => "ReplyCountChanged",
+            AppEvent::ReactionChanged(_) => "ReactionChanged",
+            AppEvent::ChatRead(_) => "ChatRead",
+            AppEvent::Mentioned(_) => "Mentioned",
+            AppEvent::PinChanged(_) => "PinChanged",
+            AppEvent::Typing(_) => "Typing",
+            AppEvent::PresenceChanged(_) => "PresenceChanged",
+            AppEvent::Resync => "Resync",
         };
-        let v = serde_json::to_string(&v).expect("Failed to serialize event");
-        debug!("Sending event {}: {:?}", name, v);
-        Ok(Event::default().data(v).event(name))
+        let v = serde_json::to_string(&envelope.event).expect("Failed to serialize event");
+        debug!("Sending event {} {}: {:?}", envelope.id, name, v);
+        Ok(Event::default()
+            .id(format_event_id(instance_id, envelope.id))
+            .data(v)
+            .event(name))
     });
 
     Sse::new(stream).keep_alive(
@@ -0,0 +1,148 @@
+use crate::{
+    error::ErrorOutput,
+    presence::{PresenceStatus, ReportPresence, ReportTyping},
+    replay::{format_event_id, ResumeFrom, ResumeParams},
+    AppError, AppState, EventEnvelope,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::IntoResponse,
+    Extension,
+};
+use chat_core::User;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+// keeps idle connections from being closed by proxies
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Frames sent by clients, tagged by `type`, e.g. `{"type": "Typing", "chatId": 1, "typing": true}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ClientFrame {
+    Typing(ReportTyping),
+    Presence(ReportPresence),
+    Read(ReportRead),
+    Ack(Ack),
+}
+
+/// The user read the chat up to the message, or up to the latest one if not given
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportRead {
+    #[serde(alias = "chat_id")]
+    chat_id: u64,
+    #[serde(default, alias = "message_id")]
+    message_id: Option<u64>,
+}
+
+/// The client received the events up to this one, by `eventId`
+#[derive(Debug, Deserialize)]
+struct Ack {
+    id: String,
+}
+
+/// Events are sent as text frames with the same JSON as the data of SSE events, plus the
+/// `eventId` to resume after. Client frames are handled on the same connection, failed
+/// ones get an error frame back.
+pub(crate) async fn ws_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(params): Query<ResumeParams>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let from = params.resume_from(&headers, state.instance_id);
+    ws.on_upgrade(move |socket| handle_socket(socket, user.id as _, from, state))
+}
+
+async fn handle_socket(socket: WebSocket, user_id: u64, from: ResumeFrom, state: AppState) {
+    let mut subscription = state.subscribe(user_id, from);
+    info!("User {} connected over websocket from {:?}", user_id, from);
+    if let Err(e) = state.set_presence(user_id, PresenceStatus::Online).await {
+        warn!("failed to set presence of user {}: {}", user_id, e);
+    }
+
+    let (mut sender, mut receiver) = socket.split();
+    let mut ping = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    loop {
+        let message = tokio::select! {
+            envelope = subscription.recv() => match envelope {
+                Some(envelope) => {
+                    debug!("Sending event {} to user {}", envelope.id, user_id);
+                    event_frame(state.instance_id, &envelope)
+                }
+                None => break,
+            },
+            frame = receiver.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    match state.handle_client_frame(user_id, &text).await {
+                        Ok(()) => continue,
+                        Err(e) => {
+                            let output = ErrorOutput::new(e.to_string());
+                            let v = serde_json::to_string(&output).expect("Failed to serialize");
+                            Message::Text(v)
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                // pings are answered by axum
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    warn!("websocket error of user {}: {}", user_id, e);
+                    break;
+                }
+            },
+            _ = ping.tick() => Message::Ping(vec![]),
+        };
+        if sender.send(message).await.is_err() {
+            break;
+        }
+    }
+    info!("User {} disconnected from websocket", user_id);
+}
+
+fn event_frame(instance_id: u64, envelope: &EventEnvelope) -> Message {
+    let mut v = serde_json::to_value(&envelope.event).expect("Failed to serialize event");
+    v["eventId"] = format_event_id(instance_id, envelope.id).into();
+    Message::Text(v.to_string())
+}
+
+impl AppState {
+    async fn handle_client_frame(&self, user_id: u64, text: &str) -> Result<(), AppError> {
+        let frame: ClientFrame = serde_json::from_str(text)?;
+        match frame {
+            ClientFrame::Typing(input) => {
+                self.set_typing(input.chat_id, user_id, input.typing).await
+            }
+            ClientFrame::Presence(input) => self.set_presence(user_id, input.status).await,
+            ClientFrame::Read(input) => self.mark_read(user_id, input).await,
+            ClientFrame::Ack(ack) => {
+                debug!("User {} acked event {}", user_id, ack.id);
+                Ok(())
+            }
+        }
+    }
+
+    // same as marking the chat read on chat_server, `chat_reads` triggers the `ChatRead` event
+    async fn mark_read(&self, user_id: u64, input: ReportRead) -> Result<(), AppError> {
+        let chat_id = input.chat_id;
+        let members = self.fetch_chat_members(chat_id).await?;
+        if !members.contains(&(user_id as i64)) {
+            return Err(AppError::NotChatMemberError { user_id, chat_id });
+        }
+
+        let message_id = input.message_id.map(|id| id as i64);
+        let read =
+            chat_core::mark_chat_read(&self.pool, chat_id as _, user_id as _, message_id).await?;
+        read.map(|_| ())
+            .ok_or_else(|| AppError::NotFound(format!("message id {}", message_id.unwrap_or(0))))
+    }
+}
@@ -136,6 +136,21 @@ export async function sendNavigationEvent(context, token, from, to) {
     await sendEvent(event, token);
 }
 
+export async function sendMessageReactedEvent(context, token, chatId, emoji, added) {
+    const event = create(AnalyticsEventSchema, {
+        context,
+        eventType: {
+            case: "messageReacted",
+            value: {
+                chatId,
+                emoji,
+                added,
+            }
+        }
+    });
+    await sendEvent(event, token);
+}
+
 async function sendEvent(event, token) {
     console.log("event:", event);
     try {
@@ -8,7 +8,7 @@ import { enumDesc, fileDesc, messageDesc, tsEnum } from "@bufbuild/protobuf/code
  * Describes the file messages.proto.
  */
 export const file_messages = /*@__PURE__*/
-  fileDesc("Cg5tZXNzYWdlcy5wcm90bxIJYW5hbHl0aWNzIr0ECg5BbmFseXRpY3NFdmVudBIoCgdjb250ZXh0GAEgASgLMhcuYW5hbHl0aWNzLkV2ZW50Q29udGV4dBItCglhcHBfc3RhcnQYCCABKAsyGC5hbmFseXRpY3MuQXBwU3RhcnRFdmVudEgAEisKCGFwcF9leGl0GAkgASgLMhcuYW5hbHl0aWNzLkFwcEV4aXRFdmVudEgAEi8KCnVzZXJfbG9naW4YCiABKAsyGS5hbmFseXRpY3MuVXNlckxvZ2luRXZlbnRIABIxCgt1c2VyX2xvZ291dBgLIAEoCzIaLmFuYWx5dGljcy5Vc2VyTG9nb3V0RXZlbnRIABI1Cg11c2VyX3JlZ2lzdGVyGAwgASgLMhwuYW5hbHl0aWNzLlVzZXJSZWdpc3RlckV2ZW50SAASMwoMY2hhdF9jcmVhdGVkGA0gASgLMhsuYW5hbHl0aWNzLkNoYXRDcmVhdGVkRXZlbnRIABIzCgxtZXNzYWdlX3NlbnQYDiABKAsyGy5hbmFseXRpY3MuTWVzc2FnZVNlbnRFdmVudEgAEjEKC2NoYXRfam9pbmVkGA8gASgLMhouYW5hbHl0aWNzLkNoYXRKb2luZWRFdmVudEgAEi0KCWNoYXRfbGVmdBgQIAEoCzIYLmFuYWx5dGljcy5DaGF0TGVmdEV2ZW50SAASMAoKbmF2aWdhdGlvbhgRIAEoCzIaLmFuYWx5dGljcy5OYXZpZ2F0aW9uRXZlbnRIAEIMCgpldmVudF90eXBlIg8KDUFwcFN0YXJ0RXZlbnQimAEKDEFwcEV4aXRFdmVudBIzCglleGl0X2NvZGUYASABKA4yIC5hbmFseXRpY3MuQXBwRXhpdEV2ZW50LkV4aXRDb2RlIlMKCEV4aXRDb2RlEhkKFUVYSVRfQ09ERV9VTlNQRUNJRklFRBAAEhUKEUVYSVRfQ09ERV9TVUNDRVNTEAESFQoRRVhJVF9DT0RFX0ZBSUxVUkUQAiIfCg5Vc2VyTG9naW5FdmVudBINCgVlbWFpbBgBIAEoCSIgCg9Vc2VyTG9nb3V0RXZlbnQSDQoFZW1haWwYASABKAkiOAoRVXNlclJlZ2lzdGVyRXZlbnQSDQoFZW1haWwYASABKAkSFAoMd29ya3NwYWNlX2lkGAIgASgJIigKEENoYXRDcmVhdGVkRXZlbnQSFAoMd29ya3NwYWNlX2lkGAEgASgJIlQKEE1lc3NhZ2VTZW50RXZlbnQSDwoHY2hhdF9pZBgBIAEoCRIMCgR0eXBlGAIgASgJEgwKBHNpemUYAyABKAUSEwoLdG90YWxfZmlsZXMYBCABKAUiIgoPQ2hhdEpvaW5lZEV2ZW50Eg8KB2NoYXRfaWQYASABKAkiIAoNQ2hhdExlZnRFdmVudBIPCgdjaGF0X2lkGAEgASgJIisKD05hdmlnYXRpb25FdmVudBIMCgRmcm9tGAEgASgJEgoKAnRvGAIgASgJItkBCgxFdmVudENvbnRleHQSEQoJY2xpZW50X2lkGAEgASgJEhMKC2FwcF92ZXJzaW9uGAIgASgJEiUKBnN5c3RlbRgDIAEoCzIVLmFuYWx5dGljcy5TeXN0ZW1JbmZvEg8KB3VzZXJfaWQYBCABKAkSCgoCaXAYBSABKAkSEgoKdXNlcl9hZ2VudBgGIAEoCRIjCgNnZW8YByABKAsyFi5hbmFseXRpY3MuR2VvTG9jYXRpb24SEQoJY2xpZW50X3RzGAggASgDEhEKCXNlcnZlcl90cxgJIAEoAyJICgpTeXN0ZW1JbmZvEgoKAm9zGAEgASgJEgwKBGFyY2gYAiABKAkSDgoGbG9jYWxlGAMgASgJEhAKCHRpbWV6b25lGAQgASgJIjwKC0dlb0xvY2F0aW9uEg8KB2NvdW50cnkYASABKAkSDgoGcmVnaW9uGAIgASgJEgwKBGNpdHkYAyABKAliBnByb3RvMw");
+  fileDesc("Cg5tZXNzYWdlcy5wcm90bxIJYW5hbHl0aWNzIvgECg5BbmFseXRpY3NFdmVudBIoCgdjb250ZXh0GAEgASgLMhcuYW5hbHl0aWNzLkV2ZW50Q29udGV4dBItCglhcHBfc3RhcnQYCCABKAsyGC5hbmFseXRpY3MuQXBwU3RhcnRFdmVudEgAEisKCGFwcF9leGl0GAkgASgLMhcuYW5hbHl0aWNzLkFwcEV4aXRFdmVudEgAEi8KCnVzZXJfbG9naW4YCiABKAsyGS5hbmFseXRpY3MuVXNlckxvZ2luRXZlbnRIABIxCgt1c2VyX2xvZ291dBgLIAEoCzIaLmFuYWx5dGljcy5Vc2VyTG9nb3V0RXZlbnRIABI1Cg11c2VyX3JlZ2lzdGVyGAwgASgLMhwuYW5hbHl0aWNzLlVzZXJSZWdpc3RlckV2ZW50SAASMwoMY2hhdF9jcmVhdGVkGA0gASgLMhsuYW5hbHl0aWNzLkNoYXRDcmVhdGVkRXZlbnRIABIzCgxtZXNzYWdlX3NlbnQYDiABKAsyGy5hbmFseXRpY3MuTWVzc2FnZVNlbnRFdmVudEgAEjEKC2NoYXRfam9pbmVkGA8gASgLMhouYW5hbHl0aWNzLkNoYXRKb2luZWRFdmVudEgAEi0KCWNoYXRfbGVmdBgQIAEoCzIYLmFuYWx5dGljcy5DaGF0TGVmdEV2ZW50SAASMAoKbmF2aWdhdGlvbhgRIAEoCzIaLmFuYWx5dGljcy5OYXZpZ2F0aW9uRXZlbnRIABI5Cg9tZXNzYWdlX3JlYWN0ZWQYEiABKAsyHi5hbmFseXRpY3MuTWVzc2FnZVJlYWN0ZWRFdmVudEgAQgwKCmV2ZW50X3R5cGUiDwoNQXBwU3RhcnRFdmVudCKYAQoMQXBwRXhpdEV2ZW50EjMKCWV4aXRfY29kZRgBIAEoDjIgLmFuYWx5dGljcy5BcHBFeGl0RXZlbnQuRXhpdENvZGUiUwoIRXhpdENvZGUSGQoVRVhJVF9DT0RFX1VOU1BFQ0lGSUVEEAASFQoRRVhJVF9DT0RFX1NVQ0NFU1MQARIVChFFWElUX0NPREVfRkFJTFVSRRACIh8KDlVzZXJMb2dpbkV2ZW50Eg0KBWVtYWlsGAEgASgJIiAKD1VzZXJMb2dvdXRFdmVudBINCgVlbWFpbBgBIAEoCSI4ChFVc2VyUmVnaXN0ZXJFdmVudBINCgVlbWFpbBgBIAEoCRIUCgx3b3Jrc3BhY2VfaWQYAiABKAkiKAoQQ2hhdENyZWF0ZWRFdmVudBIUCgx3b3Jrc3BhY2VfaWQYASABKAkiVAoQTWVzc2FnZVNlbnRFdmVudBIPCgdjaGF0X2lkGAEgASgJEgwKBHR5cGUYAiABKAkSDAoEc2l6ZRgDIAEoBRITCgt0b3RhbF9maWxlcxgEIAEoBSIiCg9DaGF0Sm9pbmVkRXZlbnQSDwoHY2hhdF9pZBgBIAEoCSIgCg1DaGF0TGVmdEV2ZW50Eg8KB2NoYXRfaWQYASABKAkiKwoPTmF2aWdhdGlvbkV2ZW50EgwKBGZyb20YASABKAkSCgoCdG8YAiABKAkiRAoTTWVzc2FnZVJlYWN0ZWRFdmVudBIPCgdjaGF0X2lkGAEgASgJEg0KBWVtb2ppGAIgASgJEg0KBWFkZGVkGAMgASgIItkBCgxFdmVudENvbnRleHQSEQoJY2xpZW50X2lkGAEgASgJEhMKC2FwcF92ZXJzaW9uGAIgASgJEiUKBnN5c3RlbRgDIAEoCzIVLmFuYWx5dGljcy5TeXN0ZW1JbmZvEg8KB3VzZXJfaWQYBCABKAkSCgoCaXAYBSABKAkSEgoKdXNlcl9hZ2VudBgGIAEoCRIjCgNnZW8YByABKAsyFi5hbmFseXRpY3MuR2VvTG9jYXRpb24SEQoJY2xpZW50X3RzGAggASgDEhEKCXNlcnZlcl90cxgJIAEoAyJICgpTeXN0ZW1JbmZvEgoKAm9zGAEgASgJEgwKBGFyY2gYAiABKAkSDgoGbG9jYWxlGAMgASgJEhAKCHRpbWV6b25lGAQgASgJIjwKC0dlb0xvY2F0aW9uEg8KB2NvdW50cnkYASABKAkSDgoGcmVnaW9uGAIgASgJEgwKBGNpdHkYAyABKAliBnByb3RvMw");
 
 /**
  * Describes the message analytics.AnalyticsEvent.
@@ -99,23 +99,30 @@ export const ChatLeftEventSchema = /*@__PURE__*/
 export const NavigationEventSchema = /*@__PURE__*/
   messageDesc(file_messages, 10);
 
+/**
+ * Describes the message analytics.MessageReactedEvent.
+ * Use `create(MessageReactedEventSchema)` to create a new message.
+ */
+export const MessageReactedEventSchema = /*@__PURE__*/
+  messageDesc(file_messages, 11);
+
 /**
  * Describes the message analytics.EventContext.
  * Use `create(EventContextSchema)` to create a new message.
  */
 export const EventContextSchema = /*@__PURE__*/
-  messageDesc(file_messages, 11);
+  messageDesc(file_messages, 12);
 
 /**
  * Describes the message analytics.SystemInfo.
  * Use `create(SystemInfoSchema)` to create a new message.
  */
 export const SystemInfoSchema = /*@__PURE__*/
-  messageDesc(file_messages, 12);
+  messageDesc(file_messages, 13);
 
 /**
  * Describes the message analytics.GeoLocation.
  * Use `create(GeoLocationSchema)` to create a new message.
  */
 export const GeoLocationSchema = /*@__PURE__*/
-  messageDesc(file_messages, 13);
+  messageDesc(file_messages, 14);
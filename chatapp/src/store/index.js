@@ -1,10 +1,10 @@
 import { createStore } from 'vuex';
 import axios from 'axios';
 import { jwtDecode } from "jwt-decode";
-import { getUrlBase } from '../utils';
+import { getUrlBase, getNotifyBase } from '../utils';
 import { initSSE } from '../utils';
 import { formatMessageDate } from '../utils';
-import { sendAppStartEvent, sendUserLoginEvent, sendUserLogoutEvent, sendUserRegisterEvent, sendChatCreatedEvent, sendMessageSentEvent, sendChatJoinedEvent, sendChatLeftEvent, sendNavigationEvent } from '../analytics/event';
+import { sendAppStartEvent, sendUserLoginEvent, sendUserLogoutEvent, sendUserRegisterEvent, sendChatCreatedEvent, sendMessageSentEvent, sendChatJoinedEvent, sendChatLeftEvent, sendNavigationEvent, sendMessageReactedEvent } from '../analytics/event';
 import { v4 as uuidv4 } from 'uuid';
 import packageJson from '../../package.json';
 
@@ -42,11 +42,16 @@ export default createStore({
     users: {},          // Users hashmap under workspace, keyed by user ID
     activeChannel: null,
     sse: null,
+    typing: {},         // User IDs typing, keyed by channel ID
+    heartbeat: null,    // Presence heartbeat timer
   },
   mutations: {
     setSSE(state, sse) {
       state.sse = sse;
     },
+    setHeartbeat(state, heartbeat) {
+      state.heartbeat = heartbeat;
+    },
     setUser(state, user) {
       state.user = user;
     },
@@ -84,10 +89,98 @@ export default createStore({
         state.messages[channelId] = [message];
       }
     },
+    updateMessage(state, { channelId, message }) {
+      const messages = state.messages[channelId] || [];
+      const index = messages.findIndex((m) => m.id === message.id);
+      if (index !== -1) {
+        message.formattedCreatedAt = formatMessageDate(message.createdAt);
+        messages.splice(index, 1, message);
+      }
+    },
+    removeMessage(state, { channelId, messageId }) {
+      const messages = state.messages[channelId] || [];
+      const index = messages.findIndex((m) => m.id === messageId);
+      if (index !== -1) {
+        messages.splice(index, 1);
+      }
+    },
+    updateReplyCount(state, { channelId, messageId, replyCount }) {
+      const messages = state.messages[channelId] || [];
+      const message = messages.find((m) => m.id === messageId);
+      if (message) {
+        message.replyCount = replyCount;
+      }
+    },
+    updateReaction(state, { channelId, messageId, userId, emoji, added }) {
+      const messages = state.messages[channelId] || [];
+      const message = messages.find((m) => m.id === messageId);
+      if (!message) {
+        return;
+      }
+      const reactions = message.reactions || [];
+      let reaction = reactions.find((r) => r.emoji === emoji);
+      if (added) {
+        if (!reaction) {
+          reaction = { emoji, count: 0, userIds: [] };
+          reactions.push(reaction);
+        }
+        if (!reaction.userIds.includes(userId)) {
+          reaction.userIds.push(userId);
+          reaction.count += 1;
+        }
+      } else if (reaction && reaction.userIds.includes(userId)) {
+        reaction.userIds = reaction.userIds.filter((id) => id !== userId);
+        reaction.count -= 1;
+      }
+      message.reactions = reactions.filter((r) => r.count > 0);
+    },
     setActiveChannel(state, channelId) {
       const channel = state.channels.find((c) => c.id === channelId);
       state.activeChannel = channel;
     },
+    setChannelRead(state, { channelId, lastReadMessageId }) {
+      const channel = state.channels.find((c) => c.id === channelId);
+      if (!channel) {
+        return;
+      }
+      channel.lastReadMessageId = lastReadMessageId;
+      channel.mentioned = false;
+      const userId = state.user && state.user.id;
+      const messages = state.messages[channelId] || [];
+      channel.unreadCount = messages.filter(
+        (m) => m.id > lastReadMessageId && m.senderId !== userId && !m.threadRootId
+      ).length;
+    },
+    updatePin(state, { channelId, messageId, pinned }) {
+      const channel = state.channels.find((c) => c.id === channelId);
+      if (!channel) {
+        return;
+      }
+      const pins = (channel.pins || []).filter((id) => id !== messageId);
+      channel.pins = pinned ? [messageId, ...pins] : pins;
+    },
+    setTyping(state, { channelId, userId, typing }) {
+      const userIds = (state.typing[channelId] || []).filter((id) => id !== userId);
+      state.typing[channelId] = typing ? [...userIds, userId] : userIds;
+    },
+    setPresence(state, { userId, status }) {
+      const user = state.users[userId];
+      if (user) {
+        user.online = status === 'online';
+      }
+    },
+    setMentioned(state, { channelId }) {
+      const channel = state.channels.find((c) => c.id === channelId);
+      if (channel) {
+        channel.mentioned = true;
+      }
+    },
+    incrementUnread(state, { channelId }) {
+      const channel = state.channels.find((c) => c.id === channelId);
+      if (channel) {
+        channel.unreadCount = (channel.unreadCount || 0) + 1;
+      }
+    },
     loadUserState(state) {
       setContext(state);
 
@@ -134,12 +227,46 @@ export default createStore({
       }
       const sse = initSSE(this);
       commit('setSSE', sse);
+
+      // notify_server takes the user offline if no heartbeat is received within a minute
+      if (state.heartbeat) {
+        clearInterval(state.heartbeat);
+      }
+      const heartbeat = setInterval(() => {
+        this.dispatch('reportPresence', document.hidden ? 'away' : 'online');
+      }, 30000);
+      commit('setHeartbeat', heartbeat);
     },
     closeSSE({ state, commit }) {
       if (state.sse) {
         state.sse.close();
         commit('setSSE', null);
       }
+      if (state.heartbeat) {
+        clearInterval(state.heartbeat);
+        commit('setHeartbeat', null);
+      }
+    },
+    async reportTyping({ state }, { channelId, typing }) {
+      try {
+        await axios.post(`${getNotifyBase()}/typing`, { chatId: channelId, typing }, {
+          headers: { Authorization: `Bearer ${state.token}` },
+        });
+      } catch (error) {
+        console.error(`Failed to report typing in channel ${channelId}:`, error);
+      }
+    },
+    async reportPresence({ state }, status) {
+      if (!state.token) {
+        return;
+      }
+      try {
+        await axios.post(`${getNotifyBase()}/presence`, { status }, {
+          headers: { Authorization: `Bearer ${state.token}` },
+        });
+      } catch (error) {
+        console.error('Failed to report presence:', error);
+      }
     },
     async signup({ commit }, { email, fullname, password, workspace }) {
       try {
@@ -192,6 +319,17 @@ export default createStore({
       commit('setActiveChannel', channel);
       console.log("setActiveChannel:", channel);
       localStorage.setItem('activeChannelId', channel);
+      this.dispatch('markChannelRead', channel);
+    },
+    async markChannelRead({ state, commit }, channelId) {
+      try {
+        const response = await network(this, 'post', `/chats/${channelId}/read`, {}, {
+          Authorization: `Bearer ${state.token}`,
+        });
+        commit('setChannelRead', { channelId, lastReadMessageId: response.data.lastReadMessageId });
+      } catch (error) {
+        console.error(`Failed to mark channel ${channelId} as read:`, error);
+      }
     },
     addChannel({ commit }, channel) {
       commit('addChannel', channel);
@@ -206,7 +344,7 @@ export default createStore({
           const response = await network(this, 'get', `/chats/${channelId}/messages`, null, {
             Authorization: `Bearer ${state.token}`,
           });
-          const messages = response.data;
+          const messages = response.data.messages;
           commit('setMessages', { channelId, messages });
         } catch (error) {
           console.error(`Failed to fetch messages for channel ${channelId}:`, error);
@@ -247,6 +385,19 @@ export default createStore({
         throw error;
       }
     },
+    async toggleReaction({ state }, { chatId, messageId, emoji, added }) {
+      try {
+        const url = `/chats/${chatId}/messages/${messageId}/reactions/${encodeURIComponent(emoji)}`;
+        await network(this, added ? 'put' : 'delete', url, null, {
+          Authorization: `Bearer ${state.token}`,
+        });
+        // local state is updated by the ReactionChanged event
+        await sendMessageReactedEvent(state.context, state.token, chatId.toString(), emoji, added);
+      } catch (error) {
+        console.error('Failed to update reaction:', error);
+        throw error;
+      }
+    },
     addMessage({ commit }, { channelId, message }) {
       commit('addMessage', { channelId, message });
     },
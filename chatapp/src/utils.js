@@ -29,6 +29,9 @@ const getSseBase = () => {
   return SSE_URL;
 }
 
+// notify_server serves `/typing` and `/presence` next to `/events`
+const getNotifyBase = () => getSseBase().replace(/\/events$/, '');
+
 const initSSE = (store) => {
   let sse_base = getSseBase();
   let url = `${sse_base}?token=${store.state.token}`;
@@ -38,7 +41,80 @@ const initSSE = (store) => {
     let data = JSON.parse(e.data);
     console.log('message:', e.data);
     delete data.event;
+    // thread replies are not shown at top level, the root message gets a new reply count
+    if (data.threadRootId) {
+      return;
+    }
     store.commit('addMessage', { channelId: data.chatId, message: data });
+    if (data.senderId !== store.state.user.id) {
+      const active = store.state.activeChannel;
+      if (active && active.id === data.chatId) {
+        store.dispatch('markChannelRead', data.chatId);
+      } else {
+        store.commit('incrementUnread', { channelId: data.chatId });
+      }
+    }
+  });
+
+  sse.addEventListener("MessageUpdated", (e) => {
+    let data = JSON.parse(e.data);
+    delete data.event;
+    store.commit('updateMessage', { channelId: data.chatId, message: data });
+  });
+
+  sse.addEventListener("MessageDeleted", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('removeMessage', { channelId: data.chatId, messageId: data.id });
+  });
+
+  // the user read the chat on this or another device
+  sse.addEventListener("ChatRead", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('setChannelRead', { channelId: data.chatId, lastReadMessageId: data.lastReadMessageId });
+  });
+
+  sse.addEventListener("ReplyCountChanged", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('updateReplyCount', {
+      channelId: data.chatId,
+      messageId: data.messageId,
+      replyCount: data.replyCount,
+    });
+  });
+
+  sse.addEventListener("ReactionChanged", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('updateReaction', {
+      channelId: data.chatId,
+      messageId: data.messageId,
+      userId: data.userId,
+      emoji: data.emoji,
+      added: data.added,
+    });
+  });
+
+  sse.addEventListener("PinChanged", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('updatePin', { channelId: data.chatId, messageId: data.messageId, pinned: data.pinned });
+  });
+
+  // the user is mentioned in a message, highlight the chat until it's read
+  sse.addEventListener("Mentioned", (e) => {
+    let data = JSON.parse(e.data);
+    const active = store.state.activeChannel;
+    if (!active || active.id !== data.chatId) {
+      store.commit('setMentioned', { channelId: data.chatId });
+    }
+  });
+
+  sse.addEventListener("Typing", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('setTyping', { channelId: data.chatId, userId: data.userId, typing: data.typing });
+  });
+
+  sse.addEventListener("PresenceChanged", (e) => {
+    let data = JSON.parse(e.data);
+    store.commit('setPresence', { userId: data.userId, status: data.status });
   });
 
   sse.onmessage = (event) => {
@@ -47,9 +123,9 @@ const initSSE = (store) => {
     // commit('addMessage', data);
   };
 
+  // the browser reconnects by itself, resuming after the last event id it got
   sse.onerror = (error) => {
     console.error('EventSource failed:', error);
-    sse.close();
   };
 
   return sse;
@@ -57,6 +133,7 @@ const initSSE = (store) => {
 
 export {
   getUrlBase,
+  getNotifyBase,
   initSSE,
 };
 
@@ -40,7 +40,11 @@ CREATE TABLE analytics.analytics_events(
     chat_left_id Nullable(String),
     -- NavigationEvent
     navigation_from Nullable(String),
-    navigation_to Nullable(String)) ENGINE = MergeTree()
+    navigation_to Nullable(String),
+    -- MessageReactedEvent
+    reaction_chat_id Nullable(String),
+    reaction_emoji Nullable(String),
+    reaction_added Nullable(Bool)) ENGINE = MergeTree()
 ORDER BY
     (
         event_type,